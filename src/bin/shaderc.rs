@@ -0,0 +1,281 @@
+//! Validates every shader under `src/shaders` without opening a window.
+//!
+//! Each `.wgsl` file is preprocessed and validated with naga, once as is and once
+//! with every name it tests with `#ifdef`/`#ifndef` defined. Then every
+//! `set_shader_module` call under `src` and `tests` is checked against the entry
+//! points of the shader it names; the Rust files are parsed, so calls wrapped
+//! over several lines count too. Run from the crate root:
+//! ```bash
+//! cargo run --bin shaderc
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use learn_wgpu::renderer_backend::preprocessor::Preprocessor;
+use learn_wgpu::renderer_backend::reflection;
+use syn::visit::Visit;
+use wgpu::naga;
+
+/// A `set_shader_module` call found in the Rust sources.
+struct ShaderModuleCall {
+    path: PathBuf,
+    line: usize,
+    column: usize,
+    shader_filename: String,
+    entry_points: Vec<(String, naga::ShaderStage)>,
+}
+
+fn find_files(dir: &Path, extension: &str, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        if path.is_dir() {
+            find_files(&path, extension, files);
+        } else if path.extension().is_some_and(|e| e == extension) {
+            files.push(path);
+        }
+    }
+}
+
+/// Names the shader tests with `#ifdef` or `#ifndef`.
+fn find_features(path: &Path) -> Vec<String> {
+    let source_code = fs::read_to_string(path).unwrap_or_default();
+    let mut features: Vec<String> = source_code
+        .lines()
+        .filter_map(|line| {
+            let directive = line.trim_start().strip_prefix('#')?;
+            let mut words = directive.split_whitespace();
+            match words.next()? {
+                "ifdef" | "ifndef" => Some(words.next()?.to_string()),
+                _ => None,
+            }
+        })
+        .collect();
+    features.sort();
+    features.dedup();
+    features
+}
+
+/// Collects the `set_shader_module` calls in a parsed Rust file, and where the
+/// ones are whose arguments aren't all string literals.
+struct CallVisitor<'a> {
+    path: &'a Path,
+    calls: Vec<ShaderModuleCall>,
+    unreadable: Vec<(usize, usize)>,
+}
+
+impl<'ast> Visit<'ast> for CallVisitor<'_> {
+    // Unit tests build shaders they write to a temporary directory themselves.
+    fn visit_item_mod(&mut self, item: &'ast syn::ItemMod) {
+        let is_test = item.attrs.iter().any(|attribute| {
+            attribute.path().is_ident("cfg")
+                && attribute
+                    .parse_args::<syn::Ident>()
+                    .is_ok_and(|ident| ident == "test")
+        });
+        if !is_test {
+            syn::visit::visit_item_mod(self, item);
+        }
+    }
+
+    fn visit_expr_method_call(&mut self, call: &'ast syn::ExprMethodCall) {
+        if call.method == "set_shader_module" {
+            let start = call.method.span().start();
+            let (line, column) = (start.line, start.column + 1);
+            let arguments: Option<Vec<String>> = call
+                .args
+                .iter()
+                .map(|argument| match argument {
+                    syn::Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Str(literal),
+                        ..
+                    }) => Some(literal.value()),
+                    _ => None,
+                })
+                .collect();
+
+            // Render pipelines name a vertex and a fragment entry point, compute
+            // pipelines a single compute one.
+            let entry_points = match arguments.as_deref() {
+                Some([_, vertex, fragment]) => Some(vec![
+                    (vertex.clone(), naga::ShaderStage::Vertex),
+                    (fragment.clone(), naga::ShaderStage::Fragment),
+                ]),
+                Some([_, compute]) => Some(vec![(compute.clone(), naga::ShaderStage::Compute)]),
+                _ => None,
+            };
+            match (arguments, entry_points) {
+                (Some(arguments), Some(entry_points)) => self.calls.push(ShaderModuleCall {
+                    path: self.path.to_path_buf(),
+                    line,
+                    column,
+                    shader_filename: arguments[0].clone(),
+                    entry_points,
+                }),
+                _ => self.unreadable.push((line, column)),
+            }
+        }
+
+        syn::visit::visit_expr_method_call(self, call);
+    }
+}
+
+/// Adds the file's `set_shader_module` calls to `calls`. Returns an error for
+/// every call that can't be checked and for a file that doesn't parse.
+fn find_shader_module_calls(path: &Path, calls: &mut Vec<ShaderModuleCall>) -> Vec<String> {
+    let source_code = fs::read_to_string(path).unwrap_or_default();
+    let file = match syn::parse_file(&source_code) {
+        Ok(file) => file,
+        Err(err) => {
+            let start = err.span().start();
+            return vec![format!(
+                "{}:{}:{}: can't parse: {}",
+                path.display(),
+                start.line,
+                start.column + 1,
+                err
+            )];
+        }
+    };
+
+    let mut visitor = CallVisitor {
+        path,
+        calls: Vec::new(),
+        unreadable: Vec::new(),
+    };
+    visitor.visit_file(&file);
+    calls.append(&mut visitor.calls);
+
+    visitor
+        .unreadable
+        .into_iter()
+        .map(|(line, column)| {
+            format!(
+                "{}:{}:{}: can't check this set_shader_module call; pass the shader and entry points as string literals",
+                path.display(),
+                line,
+                column
+            )
+        })
+        .collect()
+}
+
+/// Validates the shader once per feature, returning its module on success.
+fn validate_shader(path: &Path) -> Result<naga::Module, Vec<String>> {
+    let mut preprocessors = vec![Preprocessor::new()];
+    for feature in find_features(path) {
+        let mut preprocessor = Preprocessor::new();
+        preprocessor.define(&feature, "");
+        preprocessors.push(preprocessor);
+    }
+
+    let mut errors = Vec::new();
+    let mut default_module = None;
+    for preprocessor in preprocessors {
+        let result = preprocessor
+            .process(path)
+            .map_err(|err| err.to_string())
+            .and_then(|shader| reflection::parse_wgsl(&shader));
+        let defines = preprocessor.get_defines();
+        match result {
+            Ok((module, _)) if default_module.is_none() => default_module = Some(module),
+            Ok(_) => {}
+            Err(message) if defines.is_empty() => errors.push(message),
+            Err(message) => errors.push(format!("{} (with {} defined)", message, defines[0].0)),
+        }
+    }
+
+    match default_module {
+        Some(module) if errors.is_empty() => Ok(module),
+        _ => Err(errors),
+    }
+}
+
+fn main() -> ExitCode {
+    let mut shader_paths = Vec::new();
+    find_files(Path::new("src/shaders"), "wgsl", &mut shader_paths);
+    if shader_paths.is_empty() {
+        eprintln!("error: no shaders found under src/shaders; run from the crate root");
+        return ExitCode::FAILURE;
+    }
+
+    let mut failures = 0;
+    let mut modules = Vec::new();
+    for path in &shader_paths {
+        match validate_shader(path) {
+            Ok(module) => {
+                println!("ok: {}", path.display());
+                modules.push((path.clone(), module));
+            }
+            Err(errors) => {
+                for error in errors {
+                    eprintln!("error: {}", error);
+                    failures += 1;
+                }
+            }
+        }
+    }
+
+    let mut source_paths = Vec::new();
+    find_files(Path::new("src"), "rs", &mut source_paths);
+    find_files(Path::new("tests"), "rs", &mut source_paths);
+    let mut calls = Vec::new();
+    for path in &source_paths {
+        for error in find_shader_module_calls(path, &mut calls) {
+            eprintln!("error: {}", error);
+            failures += 1;
+        }
+    }
+
+    for call in &calls {
+        let location = format!("{}:{}:{}", call.path.display(), call.line, call.column);
+        let shader_path = Path::new("src").join(&call.shader_filename);
+        let Some((_, module)) = modules.iter().find(|(path, _)| *path == shader_path) else {
+            if !shader_paths.contains(&shader_path) {
+                eprintln!(
+                    "error: {}: no shader at {}",
+                    location,
+                    shader_path.display()
+                );
+                failures += 1;
+            }
+            continue;
+        };
+
+        for (name, stage) in &call.entry_points {
+            let found = module
+                .entry_points
+                .iter()
+                .any(|entry_point| entry_point.name == *name && entry_point.stage == *stage);
+            if !found {
+                eprintln!(
+                    "error: {}: {} has no {:?} entry point named '{}'",
+                    location,
+                    shader_path.display(),
+                    stage,
+                    name
+                );
+                failures += 1;
+            }
+        }
+    }
+
+    if failures > 0 {
+        eprintln!("{} error(s)", failures);
+        return ExitCode::FAILURE;
+    }
+    println!(
+        "Validated {} shader(s) and {} set_shader_module call(s)",
+        shader_paths.len(),
+        calls.len()
+    );
+    ExitCode::SUCCESS
+}
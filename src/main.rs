@@ -1,23 +1,51 @@
-mod renderer_backend;
-
 use futures::executor::block_on;
-use renderer_backend::material::Material;
-use renderer_backend::pipeline::RenderPipelineBuilder;
-use renderer_backend::{bind_group_layout, mesh_builder};
+use learn_wgpu::renderer_backend;
+use renderer_backend::bindless::{self, BindlessMaterials};
+use renderer_backend::compute::{self, StorageBuffer};
+use renderer_backend::depth_texture::DepthTexture;
+use renderer_backend::hot_reload::{
+    PipelineId, ReloadablePipeline, ReloadablePipelines, ShaderWatcher, WatchedPaths,
+};
+use renderer_backend::image_loader::{ImageLoader, LoadedImage};
+use renderer_backend::material::{LoadOptions, Material};
+use renderer_backend::multisample::{self, MultisampledTexture};
+use renderer_backend::offscreen::OffscreenTarget;
+use renderer_backend::pipeline::{ComputePipelineBuilder, RenderPipelineBuilder};
+use renderer_backend::pipeline_cache::{self, PipelineCache};
+use renderer_backend::push_constants::PushConstants;
+use renderer_backend::resource_cache::ResourceCache;
+use renderer_backend::viewer::ImageViewer;
+use renderer_backend::{bind_group, bind_group_layout, mesh_builder};
+use std::path::Path;
 use std::sync::Arc;
-use winit::dpi::PhysicalSize;
+use winit::dpi::{PhysicalPosition, PhysicalSize};
 
 use wgpu::{
-    Adapter, ComputePipeline, RenderPipeline, RequestAdapterOptions, RequestAdapterOptionsBase,
-    SurfaceTarget,
+    Adapter, ComputePipeline, RequestAdapterOptions, RequestAdapterOptionsBase, SurfaceTarget,
 };
 use winit::application::ApplicationHandler;
-use winit::event::WindowEvent;
-use winit::event_loop::{ActiveEventLoop, EventLoop};
+use winit::event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent};
+use winit::event_loop::{ActiveEventLoop, EventLoop, EventLoopProxy};
+use winit::keyboard::{KeyCode, PhysicalKey};
 use winit::window::{Window, WindowId};
 
 use wgpu::{Device, Instance, Queue, Surface, SurfaceConfiguration};
 
+const MSAA_SAMPLE_COUNT: u32 = 4;
+const WIREFRAME_LINE_WIDTH: f64 = 1.5;
+/// Per-draw tint, multiplied into the shaded color.
+const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+/// Images of the quad and the triangle.
+const QUAD_IMAGE: &str = "img/satin.jpg";
+const TRIANGLE_IMAGE: &str = "img/rezero.jpg";
+/// Positions of the materials in the bindless texture array.
+const QUAD_MATERIAL: u32 = 0;
+const TRIANGLE_MATERIAL: u32 = 1;
+/// Image viewer zoom per line of mouse wheel scrolling, and how many pixels of
+/// touchpad scrolling make a line.
+const ZOOM_PER_LINE: f32 = 1.2;
+const PIXELS_PER_LINE: f64 = 40.0;
+
 #[derive(Default)]
 struct App<'a> {
     window: Option<Arc<Window>>,
@@ -27,16 +55,42 @@ struct App<'a> {
     queue: Option<Queue>,
     config: Option<SurfaceConfiguration>,
     size: (u32, u32),
-    render_pipeline: Option<RenderPipeline>,
+    pipelines: ReloadablePipelines,
+    render_pipeline: Option<PipelineId>,
+    wireframe_pipeline: Option<PipelineId>,
+    wireframe: bool,
+    barycentric_wireframe: bool,
     compute_pipeline: Option<ComputePipeline>,
     triangle_mesh: Option<wgpu::Buffer>,
     quad_mesh: Option<mesh_builder::Mesh>,
+    quad_unindexed_mesh: Option<wgpu::Buffer>,
     triangle_material: Option<Material>,
     quad_material: Option<Material>,
+    material_bind_group_layout: Option<bind_group_layout::BindGroupLayout>,
+    bindless_materials: Option<BindlessMaterials>,
+    image_loader: Option<ImageLoader>,
+    offscreen: Option<OffscreenTarget>,
+    depth_texture: Option<DepthTexture>,
+    msaa_texture: Option<MultisampledTexture>,
+    sample_count: u32,
+    draw_params: Option<PushConstants>,
+    pipeline_cache: Option<PipelineCache>,
+    resource_cache: Option<ResourceCache>,
+    event_loop_proxy: Option<EventLoopProxy<CustomEvent>>,
+    /// Image to show in the pan-and-zoom viewer instead of the scene.
+    viewer_path: Option<String>,
+    viewer: Option<ImageViewer>,
+    viewer_pipeline: Option<PipelineId>,
+    watched_shaders: Option<WatchedPaths>,
+    cursor_position: PhysicalPosition<f64>,
+    dragging: bool,
 }
 
+#[allow(dead_code)]
 enum CustomEvent {
     Timer,
+    ShaderChanged,
+    ImageLoaded,
 }
 
 impl<'a> App<'a> {
@@ -92,8 +146,145 @@ impl<'a> App<'a> {
     }
 
     fn get_render_pipeline(&self) -> &wgpu::RenderPipeline {
-        self.render_pipeline.as_ref().unwrap()
+        self.pipelines.get(self.render_pipeline.unwrap())
+    }
+
+    fn get_wireframe_pipeline(&self) -> &wgpu::RenderPipeline {
+        self.pipelines.get(self.wireframe_pipeline.unwrap())
+    }
+
+    fn get_quad_unindexed_mesh(&self) -> &wgpu::Buffer {
+        self.quad_unindexed_mesh.as_ref().unwrap()
+    }
+
+    fn get_offscreen(&self) -> &OffscreenTarget {
+        self.offscreen.as_ref().unwrap()
+    }
+
+    fn get_depth_texture(&self) -> &DepthTexture {
+        self.depth_texture.as_ref().unwrap()
+    }
+
+    fn get_draw_params(&self) -> &PushConstants {
+        self.draw_params.as_ref().unwrap()
+    }
+
+    fn get_pipeline_cache(&self) -> Option<&wgpu::PipelineCache> {
+        self.pipeline_cache.as_ref().map(PipelineCache::get_cache)
+    }
+
+    fn get_resource_cache(&self) -> &ResourceCache {
+        self.resource_cache.as_ref().unwrap()
+    }
+
+    fn get_material_bind_group_layout(&self) -> &bind_group_layout::BindGroupLayout {
+        self.material_bind_group_layout.as_ref().unwrap()
+    }
+
+    fn get_viewer_pipeline(&self) -> &wgpu::RenderPipeline {
+        self.pipelines.get(self.viewer_pipeline.unwrap())
+    }
+
+    fn update_watched_shaders(&self) {
+        if let Some(watched_shaders) = self.watched_shaders.as_ref() {
+            watched_shaders.set(self.pipelines.get_shader_paths());
+        }
+    }
+
+    /// Streams in the tiles the viewer needs, if it is open. Returns true while
+    /// some are still missing.
+    fn update_viewer(&mut self) -> bool {
+        match (self.viewer.as_mut(), self.resource_cache.as_ref()) {
+            (Some(viewer), Some(resource_cache)) => viewer.update(resource_cache),
+            _ => false,
+        }
+    }
+
+    fn save_pipeline_cache(&self) {
+        if let Some(pipeline_cache) = self.pipeline_cache.as_ref() {
+            if let Err(err) = pipeline_cache.save() {
+                log::warn!("Couldn't save the pipeline cache: {}", err);
+            }
+        }
+    }
+
+    /// (Re)creates the depth buffer and, with MSAA on, the multisampled color
+    /// target for the current size.
+    fn update_render_targets(&mut self, format: wgpu::TextureFormat) {
+        let (width, height) = self.size;
+        let sample_count = self.sample_count;
+        let device = self.get_device();
+
+        let depth_texture = DepthTexture::new(device, width, height, sample_count, "Depth Texture");
+        let msaa_texture = (sample_count > 1)
+            .then(|| MultisampledTexture::new(device, width, height, format, sample_count));
+
+        self.depth_texture = Some(depth_texture);
+        self.msaa_texture = msaa_texture;
     }
+
+    /// The scene's render and wireframe pipelines, sampling either
+    /// `bindless_materials` or a bind group of `material_layout` per material.
+    fn build_scene_pipelines(
+        &self,
+        format: wgpu::TextureFormat,
+        material_layout: &bind_group_layout::BindGroupLayout,
+        bindless_materials: Option<&BindlessMaterials>,
+        draw_params: &PushConstants,
+    ) -> (ReloadablePipeline, ReloadablePipeline) {
+        let device = self.get_device();
+        let render_pipeline: ReloadablePipeline;
+        {
+            let mut builder = RenderPipelineBuilder::new(device);
+            builder.set_shader_module("shaders/shader.wgsl", "vs_main", "fs_main");
+            builder.set_pixel_format(format);
+            builder.set_depth_stencil(DepthTexture::FORMAT, wgpu::CompareFunction::LessEqual, true);
+            builder.set_sample_count(self.sample_count);
+            builder.add_vertex_buffer_layout(mesh_builder::Vertex::get_layout());
+            if let Some(materials) = bindless_materials {
+                builder.set_bindless_materials(materials);
+            }
+            builder.add_bind_group_layout(material_layout);
+            builder.set_push_constants(draw_params);
+            if let Some(cache) = self.get_pipeline_cache() {
+                builder.set_pipeline_cache(cache);
+            }
+            render_pipeline = builder
+                .build_reloadable("Render Pipeline")
+                .unwrap_or_else(|err| panic!("Can't build the render pipeline! {}", err));
+        }
+
+        let wireframe_pipeline: ReloadablePipeline;
+        {
+            let mut builder = RenderPipelineBuilder::new(device);
+            if self.barycentric_wireframe {
+                builder.set_shader_module("shaders/wireframe.wgsl", "vs_main", "fs_main");
+                builder.set_constant("line_width", WIREFRAME_LINE_WIDTH);
+            } else {
+                builder.set_shader_module("shaders/shader.wgsl", "vs_main", "fs_main");
+                builder.set_polygon_mode(wgpu::PolygonMode::Line);
+            }
+            builder.set_cull_mode(None);
+            builder.set_pixel_format(format);
+            builder.set_depth_stencil(DepthTexture::FORMAT, wgpu::CompareFunction::LessEqual, true);
+            builder.set_sample_count(self.sample_count);
+            builder.add_vertex_buffer_layout(mesh_builder::Vertex::get_layout());
+            if let Some(materials) = bindless_materials {
+                builder.set_bindless_materials(materials);
+            }
+            builder.add_bind_group_layout(material_layout);
+            builder.set_push_constants(draw_params);
+            if let Some(cache) = self.get_pipeline_cache() {
+                builder.set_pipeline_cache(cache);
+            }
+            wireframe_pipeline = builder
+                .build_reloadable("Wireframe Pipeline")
+                .unwrap_or_else(|err| panic!("Can't build the wireframe pipeline! {}", err));
+        }
+
+        (render_pipeline, wireframe_pipeline)
+    }
+
     async fn handle_adapter(
         &self,
         adapter_descriptor: &RequestAdapterOptions<'a, 'a>,
@@ -102,12 +293,27 @@ impl<'a> App<'a> {
             .instance
             .as_ref()
             .unwrap()
-            .request_adapter(&adapter_descriptor)
+            .request_adapter(adapter_descriptor)
             .await
             .unwrap();
+        // Line polygon mode is optional, the wireframe view falls back to a shader without it.
+        // Push constants are too, per-draw parameters fall back to a uniform buffer.
+        // Without pipeline caches every launch compiles its pipelines from scratch.
+        // Without bindless texture arrays every material gets its own bind group.
+        let optional_features = wgpu::Features::POLYGON_MODE_LINE
+            | wgpu::Features::PUSH_CONSTANTS
+            | wgpu::Features::PIPELINE_CACHE
+            | bindless::FEATURES;
+        let required_limits = wgpu::Limits {
+            max_push_constant_size: adapter.limits().max_push_constant_size,
+            max_binding_array_elements_per_shader_stage: adapter
+                .limits()
+                .max_binding_array_elements_per_shader_stage,
+            ..wgpu::Limits::downlevel_defaults()
+        };
         let device_descriptor = wgpu::DeviceDescriptor {
-            required_features: wgpu::Features::empty(),
-            required_limits: wgpu::Limits::downlevel_defaults(),
+            required_features: adapter.features() & optional_features,
+            required_limits,
             label: Some("Device"),
             ..Default::default()
         };
@@ -153,8 +359,7 @@ impl<'a> App<'a> {
             .formats
             .iter()
             .copied()
-            .filter(|f| f.is_srgb())
-            .next()
+            .find(|f| f.is_srgb())
             .unwrap_or(surface_capabilities.formats[0]);
 
         let surface_configuration = wgpu::SurfaceConfiguration {
@@ -168,102 +373,453 @@ impl<'a> App<'a> {
             desired_maximum_frame_latency: 2,
         };
 
+        self.surface
+            .as_ref()
+            .unwrap()
+            .configure(&device, &surface_configuration);
+        self.device = Some(device);
+        self.queue = Some(queue);
+        self.size = (size.width, size.height);
+
+        // PIPELINE CACHE
+        self.pipeline_cache = PipelineCache::load(
+            &adapter,
+            self.get_device(),
+            &pipeline_cache::get_cache_dir(),
+        );
+        self.sample_count = multisample::get_supported_sample_count(
+            &adapter,
+            self.get_device(),
+            &[surface_configuration.format, DepthTexture::FORMAT],
+            MSAA_SAMPLE_COUNT,
+        );
+        self.update_render_targets(surface_configuration.format);
+        self.init_scene(surface_configuration.format);
+        self.config = Some(surface_configuration);
+
+        // SHADER HOT-RELOAD
+        if let Some(proxy) = self.event_loop_proxy.clone() {
+            let watched_shaders = ShaderWatcher::new(self.pipelines.get_shader_paths())
+                .spawn(move || proxy.send_event(CustomEvent::ShaderChanged).is_ok());
+            self.watched_shaders = Some(watched_shaders);
+        }
+    }
+
+    fn init_headless(&mut self, width: u32, height: u32) {
+        // INSTANCE
+        let instance_descriptor = wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        };
+        let instance = wgpu::Instance::new(&instance_descriptor);
+        self.instance = Some(instance);
+
+        // ADAPTER: no surface to be compatible with, and no GPU required
+        let adapter_descriptor = RequestAdapterOptionsBase {
+            power_preference: wgpu::PowerPreference::default(),
+            compatible_surface: None,
+            force_fallback_adapter: true,
+        };
+
+        let (adapter, device, queue) = block_on(self.handle_adapter(&adapter_descriptor));
+        println!("Rendering headless on {}", adapter.get_info().name);
+
+        // OFFSCREEN TARGET
+        let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+        let offscreen = OffscreenTarget::new(&device, width, height, format);
+
+        self.device = Some(device);
+        self.queue = Some(queue);
+        self.size = (width, height);
+        self.offscreen = Some(offscreen);
+
+        // PIPELINE CACHE
+        self.pipeline_cache = PipelineCache::load(
+            &adapter,
+            self.get_device(),
+            &pipeline_cache::get_cache_dir(),
+        );
+        self.sample_count = multisample::get_supported_sample_count(
+            &adapter,
+            self.get_device(),
+            &[format, DepthTexture::FORMAT],
+            MSAA_SAMPLE_COUNT,
+        );
+        self.update_render_targets(format);
+        self.init_scene(format);
+    }
+
+    fn init_scene(&mut self, format: wgpu::TextureFormat) {
+        self.resource_cache = Some(ResourceCache::new(self.get_device()));
+        // WIREFRAME: line rasterization where supported, otherwise a barycentric
+        // edge shader over the unindexed meshes
+        self.barycentric_wireframe = !self
+            .get_device()
+            .features()
+            .contains(wgpu::Features::POLYGON_MODE_LINE);
+        let device = self.get_device();
+        let queue = self.get_queue();
+        let resource_cache = self.get_resource_cache();
+
         // CREATE THE MESH
-        let triangle_mesh = mesh_builder::make_triangle(&device);
-        let quad_mesh = mesh_builder::make_quad(&device);
+        let triangle_mesh = mesh_builder::make_triangle(device);
+        let quad_mesh = mesh_builder::make_quad(device);
+        let quad_unindexed_mesh = mesh_builder::make_quad_unindexed(device);
 
-        let material_bind_group_layout: wgpu::BindGroupLayout;
+        let material_bind_group_layout: bind_group_layout::BindGroupLayout;
         {
-            let mut builder = bind_group_layout::Builder::new(&device);
-            builder.add_material();
-            material_bind_group_layout = builder.build("Material Bind Group Layout");
+            let mut builder = bind_group_layout::Builder::new(device);
+            builder.add_material(0);
+            material_bind_group_layout =
+                builder.build_cached("Material Bind Group Layout", resource_cache);
         }
 
-        let render_pipeline: wgpu::RenderPipeline;
+        // MATERIALS: in a window, placeholders until the worker has decoded the
+        // images; a headless frame waits for them
+        let mut image_loader: Option<ImageLoader> = None;
+        let (quad_material, triangle_material) = match self.event_loop_proxy.clone() {
+            Some(proxy) => {
+                let loader = ImageLoader::spawn(device, move || {
+                    proxy.send_event(CustomEvent::ImageLoaded).is_ok()
+                });
+                loader.load(Path::new(QUAD_IMAGE), &LoadOptions::default());
+                loader.load(Path::new(TRIANGLE_IMAGE), &LoadOptions::default());
+                image_loader = Some(loader);
+
+                let quad_material = Material::placeholder(
+                    device,
+                    queue,
+                    resource_cache,
+                    "Quad Material",
+                    &material_bind_group_layout,
+                );
+                let triangle_material = Material::placeholder(
+                    device,
+                    queue,
+                    resource_cache,
+                    "Triangle Material",
+                    &material_bind_group_layout,
+                );
+                (quad_material, triangle_material)
+            }
+            None => {
+                let quad_material = Material::new(
+                    QUAD_IMAGE,
+                    device,
+                    queue,
+                    resource_cache,
+                    "Quad Material",
+                    &material_bind_group_layout,
+                );
+                let triangle_material = Material::new(
+                    TRIANGLE_IMAGE,
+                    device,
+                    queue,
+                    resource_cache,
+                    "Triangle Material",
+                    &material_bind_group_layout,
+                );
+                (quad_material, triangle_material)
+            }
+        };
+
+        log::debug!(
+            "{} texture(s) resident, {} KiB",
+            resource_cache.get_texture_count(),
+            resource_cache.get_texture_memory() / 1024
+        );
+
+        // BINDLESS: every material's texture in one array where the adapter can
+        // index it, otherwise a bind group per material
+        let bindless_materials = BindlessMaterials::new(
+            device,
+            resource_cache,
+            &[&quad_material, &triangle_material],
+        );
+        let material_layout = bindless_materials
+            .as_ref()
+            .map_or(&material_bind_group_layout, BindlessMaterials::get_layout);
+
+        // Two draws per frame, each with its own tint
+        let draw_params = PushConstants::new(
+            device,
+            wgpu::ShaderStages::FRAGMENT,
+            std::mem::size_of_val(&WHITE) as u32,
+            1,
+            2,
+        );
+
+        let (render_pipeline, wireframe_pipeline) = self.build_scene_pipelines(
+            format,
+            material_layout,
+            bindless_materials.as_ref(),
+            &draw_params,
+        );
+
+        let storage_bind_group_layout: bind_group_layout::BindGroupLayout;
         {
-            let mut builder = RenderPipelineBuilder::new(&device);
-            builder.set_shader_module("shaders/shader.wgsl", "vs_main", "fs_main");
-            builder.set_pixel_format(surface_configuration.format);
-            builder.add_vertex_buffer_layout(mesh_builder::Vertex::get_layout());
-            builder.add_bind_group_layout(&material_bind_group_layout);
-            render_pipeline = builder.build("Render Pipeline");
+            let mut builder = bind_group_layout::Builder::new(device);
+            builder.add_storage_buffer(0, wgpu::ShaderStages::COMPUTE, false);
+            storage_bind_group_layout =
+                builder.build_cached("Storage Bind Group Layout", resource_cache);
         }
 
-        let quad_material = Material::new(
-            "img/satin.jpg",
-            &device,
-            &queue,
-            "Quad Material",
-            &material_bind_group_layout,
-        );
+        let compute_pipeline: wgpu::ComputePipeline;
+        {
+            let mut builder = ComputePipelineBuilder::new(device);
+            builder.set_shader_module("shaders/compute.wgsl", "computeSomething");
+            builder.add_bind_group_layout(&storage_bind_group_layout);
+            if let Some(cache) = self.get_pipeline_cache() {
+                builder.set_pipeline_cache(cache);
+            }
+            compute_pipeline = builder.build("Compute Pipeline");
+        }
 
-        let triangle_material = Material::new(
-            "img/rezero.jpg",
-            &device,
-            &queue,
-            "Triangle Material",
-            &material_bind_group_layout,
+        // COMPUTE: double every element of the input on the GPU
+        let input: Vec<f32> = (1..=8).map(|i| i as f32).collect();
+        let storage_buffer =
+            StorageBuffer::new(device, "Doubling Buffer", bytemuck::cast_slice(&input));
+        let storage_bind_group: wgpu::BindGroup;
+        {
+            let mut builder = bind_group::Builder::new(device);
+            builder.set_layout(&storage_bind_group_layout);
+            builder.add_buffer(0, &storage_buffer.buffer);
+            storage_bind_group = builder
+                .build("Storage Bind Group")
+                .unwrap_or_else(|err| panic!("Can't build the storage bind group! {}", err));
+        }
+        compute::dispatch(
+            device,
+            queue,
+            &compute_pipeline,
+            &storage_bind_group,
+            (input.len() as u32, 1, 1),
+        );
+        let output = storage_buffer.read(device, queue);
+        log::debug!(
+            "Compute result: {:?}",
+            bytemuck::cast_slice::<u8, f32>(&output)
         );
-        // let mut compute_pipeline_builder = ComputePipelineBuilder::new();
-        // compute_pipeline_builder.set_shader_module("shaders/shader.wgsl", "computeSomething");
-        // let compute_pipeline = compute_pipeline_builder.build(&device);
 
-        self.surface
-            .as_ref()
-            .unwrap()
-            .configure(&device, &surface_configuration);
-        self.device = Some(device);
-        self.queue = Some(queue);
-        self.config = Some(surface_configuration);
-        self.size = (size.width, size.height);
-        self.render_pipeline = Some(render_pipeline);
-        // self.compute_pipeline = Some(compute_pipeline);
+        // VIEWER: the image given with `--view`, drawn tile by tile
+        let mut viewer: Option<ImageViewer> = None;
+        let mut viewer_pipeline: Option<ReloadablePipeline> = None;
+        if let Some(path) = self.viewer_path.as_deref() {
+            let image_viewer = ImageViewer::open(
+                Path::new(path),
+                device,
+                queue,
+                &material_bind_group_layout,
+                self.size,
+            )
+            .unwrap_or_else(|err| panic!("Can't open {} in the viewer! {}", path, err));
+
+            let mut builder = RenderPipelineBuilder::new(device);
+            builder.set_shader_module("shaders/viewer.wgsl", "vs_main", "fs_main");
+            builder.set_pixel_format(format);
+            builder.set_depth_stencil(DepthTexture::FORMAT, wgpu::CompareFunction::Always, false);
+            builder.set_sample_count(self.sample_count);
+            builder.set_cull_mode(None);
+            builder.add_vertex_buffer_layout(mesh_builder::Vertex::get_layout());
+            builder.add_bind_group_layout(&material_bind_group_layout);
+            builder.set_push_constants(image_viewer.get_draw_params());
+            if let Some(cache) = self.get_pipeline_cache() {
+                builder.set_pipeline_cache(cache);
+            }
+            let pipeline = builder
+                .build_reloadable("Viewer Pipeline")
+                .unwrap_or_else(|err| panic!("Can't build the viewer pipeline! {}", err));
+
+            viewer = Some(image_viewer);
+            viewer_pipeline = Some(pipeline);
+        }
+
+        let mut pipelines = ReloadablePipelines::new();
+        self.render_pipeline = Some(pipelines.add(render_pipeline));
+        self.wireframe_pipeline = Some(pipelines.add(wireframe_pipeline));
+        self.viewer_pipeline = viewer_pipeline.map(|pipeline| pipelines.add(pipeline));
+        self.pipelines = pipelines;
+        self.compute_pipeline = Some(compute_pipeline);
         self.triangle_mesh = Some(triangle_mesh);
         self.quad_mesh = Some(quad_mesh);
+        self.quad_unindexed_mesh = Some(quad_unindexed_mesh);
         self.quad_material = Some(quad_material);
-        self.triangle_material = Some(triangle_material)
+        self.triangle_material = Some(triangle_material);
+        self.material_bind_group_layout = Some(material_bind_group_layout);
+        self.bindless_materials = bindless_materials;
+        self.image_loader = image_loader;
+        self.draw_params = Some(draw_params);
+        self.viewer = viewer;
+    }
+
+    /// Uploads the images the loader has finished and swaps them in for the
+    /// placeholders.
+    fn swap_loaded_images(&mut self) {
+        let loaded = self
+            .image_loader
+            .as_ref()
+            .map_or_else(Vec::new, ImageLoader::get_loaded);
+
+        for LoadedImage { path, image } in loaded {
+            let image = match image {
+                Ok(image) => image,
+                Err(err) => {
+                    log::error!("Keeping the placeholder for {}: {}", path.display(), err);
+                    continue;
+                }
+            };
+
+            let is_quad = path == Path::new(QUAD_IMAGE);
+            let label = if is_quad {
+                "Quad Material"
+            } else {
+                "Triangle Material"
+            };
+            let texture = self
+                .get_resource_cache()
+                .add_texture(&path, image, self.get_queue());
+            let texture = match texture {
+                Ok(texture) => texture,
+                Err(err) => {
+                    log::error!("Keeping the placeholder for {}: {}", path.display(), err);
+                    continue;
+                }
+            };
+            let material = Material::from_texture(
+                texture,
+                self.get_device(),
+                self.get_resource_cache(),
+                label,
+                self.get_material_bind_group_layout(),
+            );
+            if is_quad {
+                self.quad_material = Some(material);
+            } else {
+                self.triangle_material = Some(material);
+            }
+        }
+
+        // The bindless array holds the old views, so it is rebuilt with the same
+        // layout. Tiled images can't go in it, so then the pipelines are rebuilt
+        // without it and each material binds its own group.
+        if self.bindless_materials.is_some() {
+            let bindless_materials = BindlessMaterials::new(
+                self.get_device(),
+                self.get_resource_cache(),
+                &[self.get_quad_material(), self.get_triangle_material()],
+            );
+            if bindless_materials.is_none() {
+                log::warn!("Falling back to a bind group per material");
+                let (render_pipeline, wireframe_pipeline) = self.build_scene_pipelines(
+                    self.get_config().format,
+                    self.get_material_bind_group_layout(),
+                    None,
+                    self.get_draw_params(),
+                );
+                self.pipelines
+                    .replace(self.render_pipeline.unwrap(), render_pipeline);
+                self.pipelines
+                    .replace(self.wireframe_pipeline.unwrap(), wireframe_pipeline);
+                self.update_watched_shaders();
+            }
+            self.bindless_materials = bindless_materials;
+        }
+
+        // The placeholders swapped out never went through the cache and are gone
+        // with their materials; this drops cached textures no material uses now
+        let resource_cache = self.get_resource_cache();
+        resource_cache.evict_unused_textures();
+        log::debug!(
+            "{} texture(s) resident, {} KiB",
+            resource_cache.get_texture_count(),
+            resource_cache.get_texture_memory() / 1024
+        );
+        self.get_window().request_redraw();
     }
 
     fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
         println!("Rerendering...");
+        let streaming = self.update_viewer();
         let drawable = self.get_surface().get_current_texture()?;
         let image_view_descc = wgpu::TextureViewDescriptor::default();
         let image_view = drawable.texture.create_view(&image_view_descc);
 
-        let command_encoder_descriptor = wgpu::CommandEncoderDescriptor {
-            label: Some("Render Encoder"),
-        };
+        let command_buffer = self.draw_scene(&image_view);
+        self.get_queue().submit([command_buffer]);
 
-        let mut command_encoder = self
-            .get_device()
-            .create_command_encoder(&command_encoder_descriptor);
+        drawable.present();
+        if streaming {
+            self.get_window().request_redraw();
+        }
+        Ok(())
+    }
 
-        let color_attachment = wgpu::RenderPassColorAttachment {
-            view: &image_view,
-            resolve_target: None,
-            ops: wgpu::Operations {
-                load: wgpu::LoadOp::Clear(wgpu::Color {
-                    r: 0.98,
-                    g: 0.93,
-                    b: 0.80,
-                    a: 0.0,
-                }),
+    fn render_headless(&mut self, path: &str) {
+        // A single frame, so it waits for every tile
+        while self.update_viewer() {}
+        let offscreen = self.get_offscreen();
+        let command_buffer = self.draw_scene(&offscreen.view);
+        self.get_queue().submit([command_buffer]);
+
+        offscreen.save_png(self.get_device(), self.get_queue(), path);
+        println!("Wrote frame to {}", path);
+    }
+
+    /// Begins a pass over the depth buffer and the given color attachments, one per
+    /// color target of the pipelines drawn in it, e.g. color, normals and object IDs.
+    /// The attachments need the depth buffer's size and sample count.
+    fn begin_render_pass<'e>(
+        &self,
+        command_encoder: &'e mut wgpu::CommandEncoder,
+        color_attachments: &[Option<wgpu::RenderPassColorAttachment<'_>>],
+    ) -> wgpu::RenderPass<'e> {
+        let depth_stencil_attachment = wgpu::RenderPassDepthStencilAttachment {
+            view: &self.get_depth_texture().view,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(1.0),
                 store: wgpu::StoreOp::Store,
-            },
+            }),
+            stencil_ops: None,
         };
 
         let render_pass_descriptor = wgpu::RenderPassDescriptor {
             label: Some("Renderpass"),
-            color_attachments: &[Some(color_attachment)],
+            color_attachments,
+            depth_stencil_attachment: Some(depth_stencil_attachment),
             ..Default::default()
         };
+        command_encoder.begin_render_pass(&render_pass_descriptor)
+    }
 
-        {
-            let mut renderpass = command_encoder.begin_render_pass(&render_pass_descriptor);
-            renderpass.set_pipeline(&self.get_render_pipeline());
+    /// Selects the material for the following draws: by its index in the bindless
+    /// array if there is one, otherwise by binding its own bind group.
+    fn set_material(&self, renderpass: &mut wgpu::RenderPass<'_>, material: &Material, index: u32) {
+        match self.bindless_materials.as_ref() {
+            Some(materials) => materials.select(renderpass, index),
+            None => renderpass.set_bind_group(0, &material.bind_group, &[]),
+        }
+    }
 
-            // Render Quad
-            renderpass.set_bind_group(0, &self.get_quad_material().bind_group, &[]);
+    /// Draws the quad and the triangle.
+    fn draw_meshes(&self, renderpass: &mut wgpu::RenderPass<'_>) {
+        if self.wireframe {
+            renderpass.set_pipeline(self.get_wireframe_pipeline());
+        } else {
+            renderpass.set_pipeline(self.get_render_pipeline());
+        }
+
+        if let Some(materials) = self.bindless_materials.as_ref() {
+            materials.set_bind_group(renderpass, 0);
+        }
+
+        // Render Quad
+        self.set_material(renderpass, self.get_quad_material(), QUAD_MATERIAL);
+        self.get_draw_params()
+            .set(renderpass, bytemuck::bytes_of(&WHITE));
+        if self.wireframe && self.barycentric_wireframe {
+            renderpass.set_vertex_buffer(0, self.get_quad_unindexed_mesh().slice(..));
+            renderpass.draw(0..6, 0..1);
+        } else {
             let quad_mesh = self.get_quad_mesh();
             let offset = quad_mesh.offset;
             let vertex_buffer = quad_mesh.buffer.slice(..offset);
@@ -272,20 +828,62 @@ impl<'a> App<'a> {
             renderpass.set_vertex_buffer(0, vertex_buffer);
             renderpass.set_index_buffer(index_buffer, wgpu::IndexFormat::Uint16);
             renderpass.draw_indexed(0..6, 0, 0..1);
-
-            // Render Triangle
-            renderpass.set_bind_group(0, &self.get_triangle_material().bind_group, &[]);
-            renderpass.set_vertex_buffer(0, self.get_triangle_mesh().slice(..));
-            renderpass.draw(0..3, 0..1);
         }
 
-        let command_buffer1 = command_encoder.finish();
-        // let command_buffer2 = doubling_encoder.finish();
+        // Render Triangle
+        self.set_material(renderpass, self.get_triangle_material(), TRIANGLE_MATERIAL);
+        self.get_draw_params()
+            .set(renderpass, bytemuck::bytes_of(&WHITE));
+        renderpass.set_vertex_buffer(0, self.get_triangle_mesh().slice(..));
+        renderpass.draw(0..3, 0..1);
+    }
 
-        self.get_queue().submit([command_buffer1]);
+    fn draw_scene(&self, image_view: &wgpu::TextureView) -> wgpu::CommandBuffer {
+        let command_encoder_descriptor = wgpu::CommandEncoderDescriptor {
+            label: Some("Render Encoder"),
+        };
 
-        drawable.present();
-        Ok(())
+        let mut command_encoder = self
+            .get_device()
+            .create_command_encoder(&command_encoder_descriptor);
+
+        // With MSAA on, draw into the multisampled target and resolve into the image.
+        let (view, resolve_target) = match self.msaa_texture.as_ref() {
+            Some(msaa_texture) => (&msaa_texture.view, Some(image_view)),
+            None => (image_view, None),
+        };
+
+        let color_attachment = wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color {
+                    r: 0.98,
+                    g: 0.93,
+                    b: 0.80,
+                    a: 0.0,
+                }),
+                store: wgpu::StoreOp::Store,
+            },
+        };
+
+        {
+            let mut renderpass =
+                self.begin_render_pass(&mut command_encoder, &[Some(color_attachment)]);
+            match self.viewer.as_ref() {
+                Some(viewer) => {
+                    renderpass.set_pipeline(self.get_viewer_pipeline());
+                    viewer.draw(&mut renderpass);
+                }
+                None => self.draw_meshes(&mut renderpass),
+            }
+        }
+        self.get_draw_params().flush(self.get_queue());
+        if let Some(viewer) = self.viewer.as_ref() {
+            viewer.get_draw_params().flush(self.get_queue());
+        }
+
+        command_encoder.finish()
     }
 
     fn resize(&mut self, new_size: PhysicalSize<u32>) {
@@ -303,6 +901,10 @@ impl<'a> App<'a> {
 
             self.get_surface()
                 .configure(self.get_device(), self.get_config());
+            self.update_render_targets(self.get_config().format);
+            if let Some(viewer) = self.viewer.as_mut() {
+                viewer.get_camera_mut().resize(self.size);
+            }
         }
     }
 
@@ -352,6 +954,51 @@ impl<'a> ApplicationHandler<CustomEvent> for App<'a> {
                 self.update_surface();
                 self.resize(size);
             }
+            WindowEvent::KeyboardInput { event, .. }
+                if event.state == ElementState::Pressed
+                    && !event.repeat
+                    && event.physical_key == PhysicalKey::Code(KeyCode::KeyW) =>
+            {
+                self.wireframe = !self.wireframe;
+                self.get_window().request_redraw();
+            }
+            WindowEvent::KeyboardInput { event, .. }
+                if event.state == ElementState::Pressed
+                    && event.physical_key == PhysicalKey::Code(KeyCode::KeyF)
+                    && self.viewer.is_some() =>
+            {
+                self.viewer.as_mut().unwrap().get_camera_mut().fit();
+                self.get_window().request_redraw();
+            }
+            WindowEvent::MouseWheel { delta, .. } if self.viewer.is_some() => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => y,
+                    MouseScrollDelta::PixelDelta(position) => (position.y / PIXELS_PER_LINE) as f32,
+                };
+                let cursor =
+                    glm::vec2(self.cursor_position.x as f32, self.cursor_position.y as f32);
+                let camera = self.viewer.as_mut().unwrap().get_camera_mut();
+                camera.zoom_at(cursor, ZOOM_PER_LINE.powf(lines));
+                self.get_window().request_redraw();
+            }
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } => {
+                self.dragging = state == ElementState::Pressed;
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                let delta = glm::vec2(
+                    (position.x - self.cursor_position.x) as f32,
+                    (position.y - self.cursor_position.y) as f32,
+                );
+                self.cursor_position = position;
+                if let (true, Some(viewer)) = (self.dragging, self.viewer.as_mut()) {
+                    viewer.get_camera_mut().pan(delta);
+                    self.get_window().request_redraw();
+                }
+            }
             WindowEvent::CloseRequested => {
                 event_loop.exit();
             }
@@ -364,17 +1011,63 @@ impl<'a> ApplicationHandler<CustomEvent> for App<'a> {
             CustomEvent::Timer => {
                 println!("Timer event received");
             }
+            CustomEvent::ShaderChanged => {
+                if self.pipelines.reload() {
+                    self.update_watched_shaders();
+                    self.get_window().request_redraw();
+                }
+            }
+            CustomEvent::ImageLoaded => self.swap_loaded_images(),
         }
     }
 
     fn exiting(&mut self, _: &ActiveEventLoop) {
+        self.save_pipeline_cache();
         println!("Exiting! Goodbye!");
     }
 }
 
 fn main() {
+    env_logger::init();
+
+    // `cargo run -- --headless [output.png] [width] [height]` renders a single
+    // frame offscreen on the fallback adapter, without opening a window.
+    // Add `--wireframe` to render the wireframe view instead.
+    // `--view <image>` shows the image in the pan-and-zoom viewer instead of the
+    // scene, in the window or headless.
+    let args: Vec<String> = std::env::args().collect();
+    let viewer_path = args
+        .iter()
+        .position(|arg| arg == "--view")
+        .and_then(|index| args.get(index + 1))
+        .cloned();
+    if let Some(index) = args.iter().position(|arg| arg == "--headless") {
+        let output = args
+            .get(index + 1)
+            .map(String::as_str)
+            .unwrap_or("frame.png");
+        let width = args
+            .get(index + 2)
+            .and_then(|w| w.parse().ok())
+            .unwrap_or(800);
+        let height = args
+            .get(index + 3)
+            .and_then(|h| h.parse().ok())
+            .unwrap_or(600);
+
+        let mut state = App::new();
+        state.wireframe = args.iter().any(|arg| arg == "--wireframe");
+        state.viewer_path = viewer_path;
+        state.init_headless(width, height);
+        state.render_headless(output);
+        state.save_pipeline_cache();
+        return;
+    }
+
     let event_loop = EventLoop::<CustomEvent>::with_user_event().build().unwrap();
     let mut state = App::new();
+    state.event_loop_proxy = Some(event_loop.create_proxy());
+    state.viewer_path = viewer_path;
 
     // let event_loop_proxy = event_loop.create_proxy();
 
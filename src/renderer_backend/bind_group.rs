@@ -1,6 +1,159 @@
+use std::fmt;
+
+use super::bind_group_layout::BindGroupLayout;
+
+#[derive(Debug)]
+pub enum BindGroupError {
+    MissingLayout {
+        label: String,
+    },
+    UnknownBinding {
+        label: String,
+        binding: u32,
+    },
+    DuplicateBinding {
+        label: String,
+        binding: u32,
+    },
+    MissingBinding {
+        label: String,
+        binding: u32,
+    },
+    ResourceMismatch {
+        label: String,
+        binding: u32,
+        expected: String,
+        found: String,
+    },
+    SampleTypeMismatch {
+        label: String,
+        binding: u32,
+        layout: wgpu::TextureSampleType,
+        format: wgpu::TextureFormat,
+    },
+    BufferTooSmall {
+        label: String,
+        binding: u32,
+        size: u64,
+        min_size: u64,
+    },
+    ArrayLengthMismatch {
+        label: String,
+        binding: u32,
+        count: u32,
+        length: usize,
+    },
+}
+
+impl fmt::Display for BindGroupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindGroupError::MissingLayout { label } => {
+                write!(f, "bind group '{}' has no layout set", label)
+            }
+            BindGroupError::UnknownBinding { label, binding } => write!(
+                f,
+                "bind group '{}' @binding({}): the layout has no such binding",
+                label, binding
+            ),
+            BindGroupError::DuplicateBinding { label, binding } => write!(
+                f,
+                "bind group '{}' @binding({}): bound more than once",
+                label, binding
+            ),
+            BindGroupError::MissingBinding { label, binding } => write!(
+                f,
+                "bind group '{}' @binding({}): the layout declares it, but nothing is bound",
+                label, binding
+            ),
+            BindGroupError::ResourceMismatch {
+                label,
+                binding,
+                expected,
+                found,
+            } => write!(
+                f,
+                "bind group '{}' @binding({}): the layout expects {}, but got {}",
+                label, binding, expected, found
+            ),
+            BindGroupError::SampleTypeMismatch {
+                label,
+                binding,
+                layout,
+                format,
+            } => write!(
+                f,
+                "bind group '{}' @binding({}): the layout expects {:?} samples, but the texture is {:?}",
+                label, binding, layout, format
+            ),
+            BindGroupError::BufferTooSmall {
+                label,
+                binding,
+                size,
+                min_size,
+            } => write!(
+                f,
+                "bind group '{}' @binding({}): {} bytes bound, but the layout needs at least {}",
+                label, binding, size, min_size
+            ),
+            BindGroupError::ArrayLengthMismatch {
+                label,
+                binding,
+                count,
+                length,
+            } => write!(
+                f,
+                "bind group '{}' @binding({}): the layout declares a binding array of {}, but {} are bound",
+                label, binding, count, length
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BindGroupError {}
+
+/// Views can't be queried, so textures are checked through the texture the view
+/// was made from.
+enum Resource {
+    Buffer {
+        usage: wgpu::BufferUsages,
+        size: u64,
+    },
+    Texture {
+        format: wgpu::TextureFormat,
+        dimension: wgpu::TextureDimension,
+        sample_count: u32,
+        usage: wgpu::TextureUsages,
+    },
+    Sampler,
+    Array(Vec<Resource>),
+}
+
+impl Resource {
+    fn from_texture(texture: &wgpu::Texture) -> Self {
+        Resource::Texture {
+            format: texture.format(),
+            dimension: texture.dimension(),
+            sample_count: texture.sample_count(),
+            usage: texture.usage(),
+        }
+    }
+}
+
+/// Untiled materials bind a placeholder for `tiles`, tiled ones for `texture`.
+pub struct MaterialResources<'a> {
+    pub texture: &'a wgpu::Texture,
+    pub view: &'a wgpu::TextureView,
+    pub sampler: &'a wgpu::Sampler,
+    pub tile_grid: &'a wgpu::Buffer,
+    pub tiles: &'a wgpu::Texture,
+    pub tiles_view: &'a wgpu::TextureView,
+}
+
 pub struct Builder<'builder> {
     entries: Vec<wgpu::BindGroupEntry<'builder>>,
-    layout: Option<&'builder wgpu::BindGroupLayout>,
+    resources: Vec<Resource>,
+    layout: Option<&'builder BindGroupLayout>,
     device: &'builder wgpu::Device,
 }
 
@@ -8,48 +161,802 @@ impl<'builder> Builder<'builder> {
     pub fn new(device: &'builder wgpu::Device) -> Self {
         Self {
             entries: Vec::new(),
+            resources: Vec::new(),
             layout: None,
             device,
         }
     }
 
-    pub fn set_layout(&mut self, layout: &'builder wgpu::BindGroupLayout) {
+    pub fn set_layout(&mut self, layout: &'builder BindGroupLayout) {
         self.layout = Some(layout);
     }
 
-    fn get_layout(&self) -> &'builder wgpu::BindGroupLayout {
-        self.layout.as_ref().unwrap()
-    }
-
     fn reset(&mut self) {
         self.entries.clear();
+        self.resources.clear();
     }
 
-    pub fn add_material(
+    pub fn add_material(&mut self, binding: u32, resources: &MaterialResources<'builder>) {
+        self.add_texture(binding, resources.texture, resources.view);
+        self.add_sampler(binding + 1, resources.sampler);
+        self.add_buffer(binding + 2, resources.tile_grid);
+        self.add_texture(binding + 3, resources.tiles, resources.tiles_view);
+    }
+
+    pub fn add_buffer(&mut self, binding: u32, buffer: &'builder wgpu::Buffer) {
+        self.add_buffer_range(binding, buffer, 0, None);
+    }
+
+    /// With a dynamic offset layout this is the window the offset moves over.
+    pub fn add_buffer_range(
         &mut self,
+        binding: u32,
+        buffer: &'builder wgpu::Buffer,
+        offset: wgpu::BufferAddress,
+        size: Option<wgpu::BufferSize>,
+    ) {
+        self.entries.push(wgpu::BindGroupEntry {
+            binding,
+            resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                buffer,
+                offset,
+                size,
+            }),
+        });
+        self.resources.push(Resource::Buffer {
+            usage: buffer.usage(),
+            size: size.map_or(buffer.size().saturating_sub(offset), |size| size.get()),
+        });
+    }
+
+    /// `view` must have been created from `texture`.
+    pub fn add_texture(
+        &mut self,
+        binding: u32,
+        texture: &wgpu::Texture,
         view: &'builder wgpu::TextureView,
-        sampler: &'builder wgpu::Sampler,
     ) {
         self.entries.push(wgpu::BindGroupEntry {
-            binding: self.entries.len() as u32,
+            binding,
             resource: wgpu::BindingResource::TextureView(view),
         });
+        self.resources.push(Resource::from_texture(texture));
+    }
 
+    pub fn add_texture_binding_array(
+        &mut self,
+        binding: u32,
+        textures: &[&wgpu::Texture],
+        views: &'builder [&'builder wgpu::TextureView],
+    ) {
         self.entries.push(wgpu::BindGroupEntry {
-            binding: self.entries.len() as u32,
+            binding,
+            resource: wgpu::BindingResource::TextureViewArray(views),
+        });
+        self.resources.push(Resource::Array(
+            textures
+                .iter()
+                .map(|texture| Resource::from_texture(texture))
+                .collect(),
+        ));
+    }
+
+    pub fn add_sampler(&mut self, binding: u32, sampler: &'builder wgpu::Sampler) {
+        self.entries.push(wgpu::BindGroupEntry {
+            binding,
             resource: wgpu::BindingResource::Sampler(sampler),
         });
+        self.resources.push(Resource::Sampler);
+    }
+
+    fn validate(&self, label: &str) -> Result<&'builder BindGroupLayout, BindGroupError> {
+        let layout = self.layout.ok_or_else(|| BindGroupError::MissingLayout {
+            label: label.to_string(),
+        })?;
+        let partially_bound = self
+            .device
+            .features()
+            .contains(wgpu::Features::PARTIALLY_BOUND_BINDING_ARRAY);
+
+        for (index, (entry, resource)) in self.entries.iter().zip(&self.resources).enumerate() {
+            let binding = entry.binding;
+            if self.entries[..index].iter().any(|e| e.binding == binding) {
+                return Err(BindGroupError::DuplicateBinding {
+                    label: label.to_string(),
+                    binding,
+                });
+            }
+            let layout_entry = layout
+                .entries
+                .iter()
+                .find(|e| e.binding == binding)
+                .ok_or_else(|| BindGroupError::UnknownBinding {
+                    label: label.to_string(),
+                    binding,
+                })?;
+            check_resource(label, layout_entry, resource, partially_bound)?;
+        }
+
+        if let Some(missing) = layout
+            .entries
+            .iter()
+            .find(|e| !self.entries.iter().any(|entry| entry.binding == e.binding))
+        {
+            return Err(BindGroupError::MissingBinding {
+                label: label.to_string(),
+                binding: missing.binding,
+            });
+        }
+
+        Ok(layout)
     }
 
-    pub fn build(&mut self, label: &str) -> wgpu::BindGroup {
-        let desc = wgpu::BindGroupDescriptor {
-            label: Some(label),
-            entries: &self.entries,
-            layout: self.get_layout(),
-        };
-        let bind_group = self.device.create_bind_group(&desc);
+    pub fn build(&mut self, label: &str) -> Result<wgpu::BindGroup, BindGroupError> {
+        let result = self.validate(label).map(|layout| {
+            let desc = wgpu::BindGroupDescriptor {
+                label: Some(label),
+                entries: &self.entries,
+                layout,
+            };
+            self.device.create_bind_group(&desc)
+        });
         self.reset();
 
-        bind_group
+        result
+    }
+}
+
+fn describe_binding_type(ty: &wgpu::BindingType) -> String {
+    match ty {
+        wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            ..
+        } => "a uniform buffer".to_string(),
+        wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { .. },
+            ..
+        } => "a storage buffer".to_string(),
+        wgpu::BindingType::Texture {
+            view_dimension,
+            multisampled: true,
+            ..
+        } => format!("a multisampled {:?} texture", view_dimension),
+        wgpu::BindingType::Texture { view_dimension, .. } => {
+            format!("a {:?} texture", view_dimension)
+        }
+        wgpu::BindingType::StorageTexture {
+            format,
+            view_dimension,
+            ..
+        } => format!("a {:?} {:?} storage texture", view_dimension, format),
+        wgpu::BindingType::Sampler(_) => "a sampler".to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+fn describe_entry(entry: &wgpu::BindGroupLayoutEntry) -> String {
+    match entry.count {
+        Some(count) => format!(
+            "a binding array of {} ({} each)",
+            count,
+            describe_binding_type(&entry.ty)
+        ),
+        None => describe_binding_type(&entry.ty),
+    }
+}
+
+fn describe_resource(resource: &Resource) -> String {
+    match resource {
+        Resource::Buffer { usage, .. } => format!("a buffer with usage {:?}", usage),
+        Resource::Texture {
+            format,
+            dimension,
+            sample_count,
+            usage,
+        } => format!(
+            "a {:?} {:?} texture with {} sample(s) and usage {:?}",
+            dimension, format, sample_count, usage
+        ),
+        Resource::Sampler => "a sampler".to_string(),
+        Resource::Array(elements) => format!("a binding array of {}", elements.len()),
+    }
+}
+
+/// Depth textures also bind as unfilterable floats.
+fn is_sample_type_compatible(
+    layout: wgpu::TextureSampleType,
+    format: wgpu::TextureSampleType,
+) -> bool {
+    use wgpu::TextureSampleType as T;
+    match (layout, format) {
+        (T::Float { filterable }, T::Float { filterable: other }) => !filterable || other,
+        (T::Float { filterable: false }, T::Depth) => true,
+        (layout, format) => layout == format,
+    }
+}
+
+fn check_resource(
+    label: &str,
+    layout_entry: &wgpu::BindGroupLayoutEntry,
+    resource: &Resource,
+    partially_bound: bool,
+) -> Result<(), BindGroupError> {
+    match (layout_entry.count, resource) {
+        (Some(count), Resource::Array(elements)) => {
+            let length = elements.len();
+            let count = count.get();
+            let fits = if partially_bound {
+                length <= count as usize
+            } else {
+                length == count as usize
+            };
+            if !fits {
+                return Err(BindGroupError::ArrayLengthMismatch {
+                    label: label.to_string(),
+                    binding: layout_entry.binding,
+                    count,
+                    length,
+                });
+            }
+            elements
+                .iter()
+                .try_for_each(|element| check_element(label, layout_entry, element))
+        }
+        (None, Resource::Array(_)) | (Some(_), _) => Err(BindGroupError::ResourceMismatch {
+            label: label.to_string(),
+            binding: layout_entry.binding,
+            expected: describe_entry(layout_entry),
+            found: describe_resource(resource),
+        }),
+        (None, _) => check_element(label, layout_entry, resource),
+    }
+}
+
+fn check_element(
+    label: &str,
+    layout_entry: &wgpu::BindGroupLayoutEntry,
+    resource: &Resource,
+) -> Result<(), BindGroupError> {
+    let binding = layout_entry.binding;
+    let mismatch = || BindGroupError::ResourceMismatch {
+        label: label.to_string(),
+        binding,
+        expected: describe_entry(layout_entry),
+        found: describe_resource(resource),
+    };
+
+    match (layout_entry.ty, resource) {
+        (
+            wgpu::BindingType::Buffer {
+                ty,
+                min_binding_size,
+                ..
+            },
+            Resource::Buffer { usage, size },
+        ) => {
+            let required = match ty {
+                wgpu::BufferBindingType::Uniform => wgpu::BufferUsages::UNIFORM,
+                wgpu::BufferBindingType::Storage { .. } => wgpu::BufferUsages::STORAGE,
+            };
+            if !usage.contains(required) {
+                return Err(mismatch());
+            }
+            if let Some(min_size) = min_binding_size {
+                if *size < min_size.get() {
+                    return Err(BindGroupError::BufferTooSmall {
+                        label: label.to_string(),
+                        binding,
+                        size: *size,
+                        min_size: min_size.get(),
+                    });
+                }
+            }
+        }
+        (
+            wgpu::BindingType::Texture {
+                sample_type,
+                view_dimension,
+                multisampled,
+            },
+            Resource::Texture {
+                format,
+                dimension,
+                sample_count,
+                usage,
+            },
+        ) => {
+            if !usage.contains(wgpu::TextureUsages::TEXTURE_BINDING)
+                || view_dimension.compatible_texture_dimension() != *dimension
+                || multisampled != (*sample_count > 1)
+            {
+                return Err(mismatch());
+            }
+            // Combined depth-stencil formats are left to wgpu
+            if let Some(format_type) = format.sample_type(None, None) {
+                if !is_sample_type_compatible(sample_type, format_type) {
+                    return Err(BindGroupError::SampleTypeMismatch {
+                        label: label.to_string(),
+                        binding,
+                        layout: sample_type,
+                        format: *format,
+                    });
+                }
+            }
+        }
+        (
+            wgpu::BindingType::StorageTexture {
+                format: layout_format,
+                view_dimension,
+                ..
+            },
+            Resource::Texture {
+                format,
+                dimension,
+                usage,
+                ..
+            },
+        ) => {
+            if !usage.contains(wgpu::TextureUsages::STORAGE_BINDING)
+                || *format != layout_format
+                || view_dimension.compatible_texture_dimension() != *dimension
+            {
+                return Err(mismatch());
+            }
+        }
+        (wgpu::BindingType::Sampler(_), Resource::Sampler) => {}
+        _ => return Err(mismatch()),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer_backend::bind_group_layout;
+    use crate::renderer_backend::test_device::get_test_device;
+
+    fn create_layout(device: &wgpu::Device) -> BindGroupLayout {
+        let mut builder = bind_group_layout::Builder::new(device);
+        builder.add_uniform_buffer(
+            0,
+            wgpu::ShaderStages::FRAGMENT,
+            false,
+            wgpu::BufferSize::new(16),
+        );
+        builder.add_texture(
+            1,
+            wgpu::ShaderStages::FRAGMENT,
+            wgpu::TextureSampleType::Float { filterable: true },
+            wgpu::TextureViewDimension::D2,
+            false,
+        );
+        builder.add_sampler(
+            2,
+            wgpu::ShaderStages::FRAGMENT,
+            wgpu::SamplerBindingType::Filtering,
+        );
+        builder.build("Test Layout")
+    }
+
+    fn create_buffer(device: &wgpu::Device, size: u64, usage: wgpu::BufferUsages) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Test Buffer"),
+            size,
+            usage,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_texture(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        usage: wgpu::TextureUsages,
+    ) -> (wgpu::Texture, wgpu::TextureView) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Test Texture"),
+            size: wgpu::Extent3d {
+                width: 4,
+                height: 4,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        (texture, view)
+    }
+
+    struct Resources {
+        buffer: wgpu::Buffer,
+        texture: (wgpu::Texture, wgpu::TextureView),
+        sampler: wgpu::Sampler,
+    }
+
+    fn create_resources(device: &wgpu::Device) -> Resources {
+        Resources {
+            buffer: create_buffer(device, 16, wgpu::BufferUsages::UNIFORM),
+            texture: create_texture(
+                device,
+                wgpu::TextureFormat::Rgba8Unorm,
+                wgpu::TextureUsages::TEXTURE_BINDING,
+            ),
+            sampler: device.create_sampler(&wgpu::SamplerDescriptor::default()),
+        }
+    }
+
+    #[test]
+    fn builds_a_matching_bind_group() {
+        let (device, _) = get_test_device();
+        let layout = create_layout(device);
+        let resources = create_resources(device);
+
+        let mut builder = Builder::new(device);
+        builder.set_layout(&layout);
+        builder.add_buffer(0, &resources.buffer);
+        builder.add_texture(1, &resources.texture.0, &resources.texture.1);
+        builder.add_sampler(2, &resources.sampler);
+        builder.build("Test Bind Group").unwrap();
+    }
+
+    #[test]
+    fn missing_layout() {
+        let (device, _) = get_test_device();
+        let resources = create_resources(device);
+
+        let mut builder = Builder::new(device);
+        builder.add_buffer(0, &resources.buffer);
+        let err = builder.build("Test Bind Group").err().unwrap();
+
+        assert!(
+            matches!(&err, BindGroupError::MissingLayout { label } if label == "Test Bind Group"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn unknown_binding() {
+        let (device, _) = get_test_device();
+        let layout = create_layout(device);
+        let resources = create_resources(device);
+
+        let mut builder = Builder::new(device);
+        builder.set_layout(&layout);
+        builder.add_buffer(0, &resources.buffer);
+        builder.add_texture(1, &resources.texture.0, &resources.texture.1);
+        builder.add_sampler(2, &resources.sampler);
+        builder.add_sampler(3, &resources.sampler);
+        let err = builder.build("Test Bind Group").err().unwrap();
+
+        assert!(
+            matches!(err, BindGroupError::UnknownBinding { binding: 3, .. }),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn duplicate_binding() {
+        let (device, _) = get_test_device();
+        let layout = create_layout(device);
+        let resources = create_resources(device);
+
+        let mut builder = Builder::new(device);
+        builder.set_layout(&layout);
+        builder.add_buffer(0, &resources.buffer);
+        builder.add_texture(1, &resources.texture.0, &resources.texture.1);
+        builder.add_sampler(2, &resources.sampler);
+        builder.add_buffer(0, &resources.buffer);
+        let err = builder.build("Test Bind Group").err().unwrap();
+
+        assert!(
+            matches!(err, BindGroupError::DuplicateBinding { binding: 0, .. }),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn missing_binding() {
+        let (device, _) = get_test_device();
+        let layout = create_layout(device);
+        let resources = create_resources(device);
+
+        let mut builder = Builder::new(device);
+        builder.set_layout(&layout);
+        builder.add_buffer(0, &resources.buffer);
+        builder.add_sampler(2, &resources.sampler);
+        let err = builder.build("Test Bind Group").err().unwrap();
+
+        assert!(
+            matches!(err, BindGroupError::MissingBinding { binding: 1, .. }),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn wrong_kind_of_resource() {
+        let (device, _) = get_test_device();
+        let layout = create_layout(device);
+        let resources = create_resources(device);
+
+        let mut builder = Builder::new(device);
+        builder.set_layout(&layout);
+        builder.add_buffer(0, &resources.buffer);
+        builder.add_texture(1, &resources.texture.0, &resources.texture.1);
+        builder.add_buffer(2, &resources.buffer);
+        let err = builder.build("Test Bind Group").err().unwrap();
+
+        assert!(
+            matches!(
+                &err,
+                BindGroupError::ResourceMismatch { binding: 2, expected, found, .. }
+                    if expected == "a sampler" && found.starts_with("a buffer")
+            ),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn wrong_usage() {
+        let (device, _) = get_test_device();
+        let layout = create_layout(device);
+        let resources = create_resources(device);
+        let storage_buffer = create_buffer(device, 16, wgpu::BufferUsages::STORAGE);
+        let (render_target, render_target_view) = create_texture(
+            device,
+            wgpu::TextureFormat::Rgba8Unorm,
+            wgpu::TextureUsages::RENDER_ATTACHMENT,
+        );
+
+        let mut builder = Builder::new(device);
+        builder.set_layout(&layout);
+        builder.add_buffer(0, &storage_buffer);
+        builder.add_texture(1, &resources.texture.0, &resources.texture.1);
+        builder.add_sampler(2, &resources.sampler);
+        let err = builder.build("Test Bind Group").err().unwrap();
+        assert!(
+            matches!(err, BindGroupError::ResourceMismatch { binding: 0, .. }),
+            "{err}"
+        );
+
+        builder.set_layout(&layout);
+        builder.add_buffer(0, &resources.buffer);
+        builder.add_texture(1, &render_target, &render_target_view);
+        builder.add_sampler(2, &resources.sampler);
+        let err = builder.build("Test Bind Group").err().unwrap();
+        assert!(
+            matches!(err, BindGroupError::ResourceMismatch { binding: 1, .. }),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn sample_type_mismatch() {
+        let (device, _) = get_test_device();
+        let layout = create_layout(device);
+        let resources = create_resources(device);
+        let (texture, view) = create_texture(
+            device,
+            wgpu::TextureFormat::R32Uint,
+            wgpu::TextureUsages::TEXTURE_BINDING,
+        );
+
+        let mut builder = Builder::new(device);
+        builder.set_layout(&layout);
+        builder.add_buffer(0, &resources.buffer);
+        builder.add_texture(1, &texture, &view);
+        builder.add_sampler(2, &resources.sampler);
+        let err = builder.build("Test Bind Group").err().unwrap();
+
+        assert!(
+            matches!(
+                err,
+                BindGroupError::SampleTypeMismatch {
+                    binding: 1,
+                    layout: wgpu::TextureSampleType::Float { filterable: true },
+                    format: wgpu::TextureFormat::R32Uint,
+                    ..
+                }
+            ),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn buffer_too_small() {
+        let (device, _) = get_test_device();
+        let layout = create_layout(device);
+        let resources = create_resources(device);
+        let small_buffer = create_buffer(device, 8, wgpu::BufferUsages::UNIFORM);
+
+        let mut builder = Builder::new(device);
+        builder.set_layout(&layout);
+        builder.add_buffer(0, &small_buffer);
+        builder.add_texture(1, &resources.texture.0, &resources.texture.1);
+        builder.add_sampler(2, &resources.sampler);
+        let err = builder.build("Test Bind Group").err().unwrap();
+        assert!(
+            matches!(
+                err,
+                BindGroupError::BufferTooSmall {
+                    binding: 0,
+                    size: 8,
+                    min_size: 16,
+                    ..
+                }
+            ),
+            "{err}"
+        );
+
+        // A range is measured from its offset
+        builder.set_layout(&layout);
+        builder.add_buffer_range(0, &resources.buffer, 8, None);
+        builder.add_texture(1, &resources.texture.0, &resources.texture.1);
+        builder.add_sampler(2, &resources.sampler);
+        let err = builder.build("Test Bind Group").err().unwrap();
+        assert!(
+            matches!(err, BindGroupError::BufferTooSmall { size: 8, .. }),
+            "{err}"
+        );
+    }
+
+    fn texture_array_entry(count: u32) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: std::num::NonZeroU32::new(count),
+        }
+    }
+
+    fn texture(format: wgpu::TextureFormat) -> Resource {
+        Resource::Texture {
+            format,
+            dimension: wgpu::TextureDimension::D2,
+            sample_count: 1,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+        }
+    }
+
+    fn texture_array(count: usize) -> Resource {
+        Resource::Array(
+            (0..count)
+                .map(|_| texture(wgpu::TextureFormat::Rgba8Unorm))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn binding_array_of_its_count() {
+        check_resource("Test", &texture_array_entry(4), &texture_array(4), false).unwrap();
+    }
+
+    #[test]
+    fn binding_array_under_its_count() {
+        for length in 1..4 {
+            let err = check_resource(
+                "Test",
+                &texture_array_entry(4),
+                &texture_array(length),
+                false,
+            )
+            .err()
+            .unwrap();
+
+            assert!(
+                matches!(
+                    err,
+                    BindGroupError::ArrayLengthMismatch { binding: 0, count: 4, length: l, .. }
+                        if l == length
+                ),
+                "{err}"
+            );
+        }
+    }
+
+    #[test]
+    fn partially_bound_binding_array() {
+        for length in 1..=4 {
+            check_resource(
+                "Test",
+                &texture_array_entry(4),
+                &texture_array(length),
+                true,
+            )
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn binding_array_over_its_count() {
+        for partially_bound in [false, true] {
+            let err = check_resource(
+                "Test",
+                &texture_array_entry(2),
+                &texture_array(3),
+                partially_bound,
+            )
+            .err()
+            .unwrap();
+
+            assert!(
+                matches!(
+                    err,
+                    BindGroupError::ArrayLengthMismatch {
+                        binding: 0,
+                        count: 2,
+                        length: 3,
+                        ..
+                    }
+                ),
+                "{err}"
+            );
+        }
+    }
+
+    #[test]
+    fn binding_array_for_a_single_entry() {
+        let err = check_resource("Test", &texture_array_entry(0), &texture_array(1), false)
+            .err()
+            .unwrap();
+
+        assert!(
+            matches!(err, BindGroupError::ResourceMismatch { binding: 0, .. }),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn single_resource_for_a_binding_array() {
+        let resource = texture(wgpu::TextureFormat::Rgba8Unorm);
+        let err = check_resource("Test", &texture_array_entry(2), &resource, false)
+            .err()
+            .unwrap();
+
+        assert!(
+            matches!(
+                &err,
+                BindGroupError::ResourceMismatch { binding: 0, expected, .. }
+                    if expected.starts_with("a binding array of 2")
+            ),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn binding_array_elements_are_checked() {
+        let resource = Resource::Array(vec![
+            texture(wgpu::TextureFormat::Rgba8Unorm),
+            texture(wgpu::TextureFormat::R32Uint),
+        ]);
+        let err = check_resource("Test", &texture_array_entry(2), &resource, false)
+            .err()
+            .unwrap();
+        assert!(
+            matches!(
+                err,
+                BindGroupError::SampleTypeMismatch {
+                    format: wgpu::TextureFormat::R32Uint,
+                    ..
+                }
+            ),
+            "{err}"
+        );
+
+        let resource = Resource::Array(vec![Resource::Sampler, Resource::Sampler]);
+        let err = check_resource("Test", &texture_array_entry(2), &resource, false)
+            .err()
+            .unwrap();
+        assert!(
+            matches!(err, BindGroupError::ResourceMismatch { binding: 0, .. }),
+            "{err}"
+        );
     }
 }
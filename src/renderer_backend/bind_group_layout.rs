@@ -1,3 +1,23 @@
+use std::num::NonZeroU32;
+use std::ops::Deref;
+
+use super::resource_cache::ResourceCache;
+
+/// Keeps its entries so pipelines can check them against their shaders.
+#[derive(Clone)]
+pub struct BindGroupLayout {
+    pub layout: wgpu::BindGroupLayout,
+    pub entries: Vec<wgpu::BindGroupLayoutEntry>,
+}
+
+impl Deref for BindGroupLayout {
+    type Target = wgpu::BindGroupLayout;
+
+    fn deref(&self) -> &Self::Target {
+        &self.layout
+    }
+}
+
 pub struct Builder<'builder> {
     entries: Vec<wgpu::BindGroupLayoutEntry>,
     device: &'builder wgpu::Device,
@@ -15,32 +35,166 @@ impl<'builder> Builder<'builder> {
         self.entries.clear();
     }
 
-    pub fn add_material(&mut self) {
+    /// Texture, sampler, tile grid and tile array at `binding` to `binding + 3`.
+    pub fn add_material(&mut self, binding: u32) {
+        self.add_texture(
+            binding,
+            wgpu::ShaderStages::FRAGMENT,
+            wgpu::TextureSampleType::Float { filterable: true },
+            wgpu::TextureViewDimension::D2,
+            false,
+        );
+        self.add_sampler(
+            binding + 1,
+            wgpu::ShaderStages::FRAGMENT,
+            wgpu::SamplerBindingType::Filtering,
+        );
+        self.add_uniform_buffer(
+            binding + 2,
+            wgpu::ShaderStages::FRAGMENT,
+            false,
+            wgpu::BufferSize::new(16),
+        );
+        self.add_texture(
+            binding + 3,
+            wgpu::ShaderStages::FRAGMENT,
+            wgpu::TextureSampleType::Float { filterable: true },
+            wgpu::TextureViewDimension::D2Array,
+            false,
+        );
+    }
+
+    pub fn add_uniform_buffer(
+        &mut self,
+        binding: u32,
+        visibility: wgpu::ShaderStages,
+        has_dynamic_offset: bool,
+        min_binding_size: Option<wgpu::BufferSize>,
+    ) {
+        self.entries.push(wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset,
+                min_binding_size,
+            },
+            count: None,
+        });
+    }
+
+    /// Writable storage buffers aren't allowed in vertex shaders.
+    pub fn add_storage_buffer(
+        &mut self,
+        binding: u32,
+        visibility: wgpu::ShaderStages,
+        read_only: bool,
+    ) {
+        self.entries.push(wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        });
+    }
+
+    pub fn add_texture(
+        &mut self,
+        binding: u32,
+        visibility: wgpu::ShaderStages,
+        sample_type: wgpu::TextureSampleType,
+        view_dimension: wgpu::TextureViewDimension,
+        multisampled: bool,
+    ) {
+        self.entries.push(wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Texture {
+                sample_type,
+                view_dimension,
+                multisampled,
+            },
+            count: None,
+        });
+    }
+
+    /// Needs `Features::TEXTURE_BINDING_ARRAY`.
+    pub fn add_texture_binding_array(
+        &mut self,
+        binding: u32,
+        visibility: wgpu::ShaderStages,
+        sample_type: wgpu::TextureSampleType,
+        view_dimension: wgpu::TextureViewDimension,
+        count: NonZeroU32,
+    ) {
         self.entries.push(wgpu::BindGroupLayoutEntry {
-            binding: self.entries.len() as u32,
-            visibility: wgpu::ShaderStages::FRAGMENT,
+            binding,
+            visibility,
             ty: wgpu::BindingType::Texture {
-                sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type,
+                view_dimension,
                 multisampled: false,
             },
+            count: Some(count),
+        });
+    }
+
+    pub fn add_storage_texture(
+        &mut self,
+        binding: u32,
+        visibility: wgpu::ShaderStages,
+        format: wgpu::TextureFormat,
+        access: wgpu::StorageTextureAccess,
+        view_dimension: wgpu::TextureViewDimension,
+    ) {
+        self.entries.push(wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::StorageTexture {
+                access,
+                format,
+                view_dimension,
+            },
             count: None,
         });
+    }
 
+    pub fn add_sampler(
+        &mut self,
+        binding: u32,
+        visibility: wgpu::ShaderStages,
+        sampler_type: wgpu::SamplerBindingType,
+    ) {
         self.entries.push(wgpu::BindGroupLayoutEntry {
-            binding: self.entries.len() as u32,
-            visibility: wgpu::ShaderStages::FRAGMENT,
-            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Sampler(sampler_type),
             count: None,
         });
     }
 
-    pub fn build(&mut self, label: &str) -> wgpu::BindGroupLayout {
+    pub fn add_entry(&mut self, entry: wgpu::BindGroupLayoutEntry) {
+        self.entries.push(entry);
+    }
+
+    pub fn build(&mut self, label: &str) -> BindGroupLayout {
         let desc = wgpu::BindGroupLayoutDescriptor {
             label: Some(label),
             entries: &self.entries,
         };
         let layout = self.device.create_bind_group_layout(&desc);
+        let entries = std::mem::take(&mut self.entries);
+
+        BindGroupLayout { layout, entries }
+    }
+
+    /// Reuses the cache's layout with the same entries, if there is one.
+    pub fn build_cached(&mut self, label: &str, cache: &ResourceCache) -> BindGroupLayout {
+        let layout = cache.get_bind_group_layout(label, &self.entries);
         self.reset();
 
         layout
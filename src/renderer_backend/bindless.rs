@@ -0,0 +1,131 @@
+use std::num::NonZeroU32;
+
+use wgpu::util::DeviceExt;
+
+use super::bind_group;
+use super::bind_group_layout::{self, BindGroupLayout};
+use super::material::Material;
+use super::resource_cache::ResourceCache;
+
+pub const FEATURES: wgpu::Features = wgpu::Features::TEXTURE_BINDING_ARRAY
+    .union(wgpu::Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING);
+
+/// Right after the mesh's vertex buffer.
+pub const INSTANCE_SLOT: u32 = 1;
+
+/// Every material's texture in one `binding_array`, indexed per instance. Shaders
+/// see `BINDLESS` defined and the array size as `MATERIAL_COUNT`:
+/// ```wgsl
+/// @group(0) @binding(0) var myTextures: binding_array<texture_2d<f32>, MATERIAL_COUNT>;
+/// @group(0) @binding(1) var mySampler: sampler;
+/// ```
+pub struct BindlessMaterials {
+    layout: BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    indices: wgpu::Buffer,
+    count: u32,
+}
+
+impl BindlessMaterials {
+    pub fn is_supported(device: &wgpu::Device, count: u32) -> bool {
+        let limits = device.limits();
+        device.features().contains(FEATURES)
+            && count <= limits.max_binding_array_elements_per_shader_stage
+            && count <= limits.max_sampled_textures_per_shader_stage
+    }
+
+    /// Uses the first material's sampler. Returns None if the device can't index
+    /// the textures or a material is tiled.
+    pub fn new(
+        device: &wgpu::Device,
+        resource_cache: &ResourceCache,
+        materials: &[&Material],
+    ) -> Option<Self> {
+        let count = NonZeroU32::new(materials.len() as u32)?;
+        let tiled = materials.iter().any(|material| material.is_tiled());
+        if tiled || !Self::is_supported(device, count.get()) {
+            return None;
+        }
+
+        let layout: BindGroupLayout;
+        {
+            let mut builder = bind_group_layout::Builder::new(device);
+            builder.add_texture_binding_array(
+                0,
+                wgpu::ShaderStages::FRAGMENT,
+                wgpu::TextureSampleType::Float { filterable: true },
+                wgpu::TextureViewDimension::D2,
+                count,
+            );
+            builder.add_sampler(
+                1,
+                wgpu::ShaderStages::FRAGMENT,
+                wgpu::SamplerBindingType::Filtering,
+            );
+            layout = builder.build_cached("Bindless Material Layout", resource_cache);
+        }
+
+        let textures: Vec<&wgpu::Texture> = materials.iter().map(|m| &m.image.texture).collect();
+        let views: Vec<&wgpu::TextureView> = materials.iter().map(|m| &m.image.view).collect();
+        let bind_group: wgpu::BindGroup;
+        {
+            let mut builder = bind_group::Builder::new(device);
+            builder.set_layout(&layout);
+            builder.add_texture_binding_array(0, &textures, &views);
+            builder.add_sampler(1, &materials[0].image.sampler);
+            bind_group = builder
+                .build("Bindless Material Bind Group")
+                .unwrap_or_else(|err| panic!("Can't build the bindless materials! {}", err));
+        }
+
+        let indices: Vec<u32> = (0..count.get()).collect();
+        let buffer_descriptor = wgpu::util::BufferInitDescriptor {
+            label: Some("Material Indices"),
+            contents: bytemuck::cast_slice(&indices),
+            usage: wgpu::BufferUsages::VERTEX,
+        };
+        let indices = device.create_buffer_init(&buffer_descriptor);
+
+        Some(Self {
+            layout,
+            bind_group,
+            indices,
+            count: count.get(),
+        })
+    }
+
+    /// The material index, read as `@location(2)`.
+    pub fn get_instance_layout() -> wgpu::VertexBufferLayout<'static> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![2 => Uint32];
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<u32>() as u64,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &ATTRIBUTES,
+        }
+    }
+
+    pub fn get_layout(&self) -> &BindGroupLayout {
+        &self.layout
+    }
+
+    pub fn get_count(&self) -> u32 {
+        self.count
+    }
+
+    pub fn set_bind_group(&self, renderpass: &mut wgpu::RenderPass<'_>, group: u32) {
+        renderpass.set_bind_group(group, &self.bind_group, &[]);
+    }
+
+    /// Each instance after the first reads the next material, so draw from
+    /// instance 0.
+    pub fn select(&self, renderpass: &mut wgpu::RenderPass<'_>, index: u32) {
+        assert!(
+            index < self.count,
+            "Material {} out of {} bindless materials",
+            index,
+            self.count
+        );
+        let offset = index as u64 * std::mem::size_of::<u32>() as u64;
+        renderpass.set_vertex_buffer(INSTANCE_SLOT, self.indices.slice(offset..));
+    }
+}
@@ -0,0 +1,48 @@
+/// How a pipeline's fragment output is combined with what is already in the target.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    /// Overwrites the target. The only mode integer formats allow.
+    Opaque,
+    /// Classic "over" compositing for straight (non-premultiplied) alpha.
+    AlphaBlend,
+    /// "Over" compositing for colors already multiplied by their alpha.
+    PremultipliedAlpha,
+    /// Adds the alpha-weighted color to the target, leaving its alpha alone.
+    Additive,
+    /// Multiplies the target color by the fragment color.
+    Multiply,
+    Custom(wgpu::BlendState),
+}
+
+impl BlendMode {
+    pub fn get_blend_state(&self) -> Option<wgpu::BlendState> {
+        let blend_state = match self {
+            BlendMode::Opaque => return None,
+            BlendMode::AlphaBlend => wgpu::BlendState::ALPHA_BLENDING,
+            BlendMode::PremultipliedAlpha => wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+            BlendMode::Additive => wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Zero,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+            },
+            BlendMode::Multiply => wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Dst,
+                    dst_factor: wgpu::BlendFactor::Zero,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent::OVER,
+            },
+            BlendMode::Custom(blend_state) => *blend_state,
+        };
+
+        Some(blend_state)
+    }
+}
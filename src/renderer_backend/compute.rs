@@ -0,0 +1,136 @@
+use wgpu::util::DeviceExt;
+
+pub struct StorageBuffer {
+    pub buffer: wgpu::Buffer,
+    readback: wgpu::Buffer,
+    size: u64,
+}
+
+impl StorageBuffer {
+    pub fn new(device: &wgpu::Device, label: &str, contents: &[u8]) -> Self {
+        let buffer_descriptor = wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+        };
+        let buffer = device.create_buffer_init(&buffer_descriptor);
+
+        let size = contents.len() as u64;
+        let readback_descriptor = wgpu::BufferDescriptor {
+            label: Some(label),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        };
+        let readback = device.create_buffer(&readback_descriptor);
+
+        StorageBuffer {
+            buffer,
+            readback,
+            size,
+        }
+    }
+
+    /// Blocks until the contents are back on the CPU.
+    pub fn read(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<u8> {
+        let command_encoder_descriptor = wgpu::CommandEncoderDescriptor {
+            label: Some("Readback Encoder"),
+        };
+        let mut command_encoder = device.create_command_encoder(&command_encoder_descriptor);
+        command_encoder.copy_buffer_to_buffer(&self.buffer, 0, &self.readback, 0, self.size);
+        queue.submit([command_encoder.finish()]);
+
+        read_buffer(device, &self.readback)
+    }
+}
+
+/// Maps a `MAP_READ` buffer, waits for the device and returns a copy of its contents.
+pub fn read_buffer(device: &wgpu::Device, buffer: &wgpu::Buffer) -> Vec<u8> {
+    let slice = buffer.slice(..);
+    let (sender, receiver) = futures::channel::oneshot::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    device.poll(wgpu::PollType::Wait).unwrap();
+    futures::executor::block_on(receiver)
+        .unwrap()
+        .expect("Couldn't map buffer for reading");
+
+    let bytes = slice.get_mapped_range().to_vec();
+    buffer.unmap();
+
+    bytes
+}
+
+pub fn dispatch(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    pipeline: &wgpu::ComputePipeline,
+    bind_group: &wgpu::BindGroup,
+    workgroups: (u32, u32, u32),
+) {
+    let command_encoder_descriptor = wgpu::CommandEncoderDescriptor {
+        label: Some("Compute Encoder"),
+    };
+    let mut command_encoder = device.create_command_encoder(&command_encoder_descriptor);
+
+    {
+        let compute_pass_descriptor = wgpu::ComputePassDescriptor {
+            label: Some("Computepass"),
+            timestamp_writes: None,
+        };
+        let mut computepass = command_encoder.begin_compute_pass(&compute_pass_descriptor);
+        computepass.set_pipeline(pipeline);
+        computepass.set_bind_group(0, bind_group, &[]);
+        computepass.dispatch_workgroups(workgroups.0, workgroups.1, workgroups.2);
+    }
+
+    queue.submit([command_encoder.finish()]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer_backend::bind_group;
+    use crate::renderer_backend::bind_group_layout;
+    use crate::renderer_backend::pipeline::ComputePipelineBuilder;
+    use crate::renderer_backend::test_device::get_test_device;
+
+    #[test]
+    fn doubles_the_storage_buffer() {
+        let (device, queue) = get_test_device();
+
+        let mut builder = bind_group_layout::Builder::new(device);
+        builder.add_storage_buffer(0, wgpu::ShaderStages::COMPUTE, false);
+        let layout = builder.build("Storage Bind Group Layout");
+
+        let mut builder = ComputePipelineBuilder::new(device);
+        builder.set_shader_module("shaders/compute.wgsl", "computeSomething");
+        builder.add_bind_group_layout(&layout);
+        let pipeline = builder.build("Compute Pipeline");
+
+        let input: Vec<f32> = (1..=8).map(|i| i as f32).collect();
+        let storage_buffer =
+            StorageBuffer::new(device, "Doubling Buffer", bytemuck::cast_slice(&input));
+        let mut builder = bind_group::Builder::new(device);
+        builder.set_layout(&layout);
+        builder.add_buffer(0, &storage_buffer.buffer);
+        let bind_group = builder.build("Storage Bind Group").unwrap();
+
+        dispatch(
+            device,
+            queue,
+            &pipeline,
+            &bind_group,
+            (input.len() as u32, 1, 1),
+        );
+        let output = storage_buffer.read(device, queue);
+
+        assert_eq!(
+            bytemuck::cast_slice::<u8, f32>(&output),
+            [2.0, 4.0, 6.0, 8.0, 10.0, 12.0, 14.0, 16.0]
+        );
+    }
+}
@@ -0,0 +1,37 @@
+pub struct DepthTexture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+}
+
+impl DepthTexture {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+    pub fn new(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+        label: &str,
+    ) -> Self {
+        let texture_size = wgpu::Extent3d {
+            depth_or_array_layers: 1,
+            width,
+            height,
+        };
+
+        let texture_descriptor = wgpu::TextureDescriptor {
+            label: Some(label),
+            mip_level_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            sample_count,
+            size: texture_size,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        };
+        let texture = device.create_texture(&texture_descriptor);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        DepthTexture { texture, view }
+    }
+}
@@ -0,0 +1,303 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use super::pipeline::RenderPipelineBuilder;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A render pipeline that keeps its builder to recompile itself.
+pub struct ReloadablePipeline {
+    pipeline: wgpu::RenderPipeline,
+    builder: RenderPipelineBuilder,
+    label: String,
+    shader_paths: Vec<PathBuf>,
+}
+
+impl ReloadablePipeline {
+    pub(crate) fn new(
+        pipeline: wgpu::RenderPipeline,
+        builder: RenderPipelineBuilder,
+        label: &str,
+        shader_paths: Vec<PathBuf>,
+    ) -> Self {
+        Self {
+            pipeline,
+            builder,
+            label: label.to_string(),
+            shader_paths,
+        }
+    }
+
+    pub fn get_pipeline(&self) -> &wgpu::RenderPipeline {
+        &self.pipeline
+    }
+
+    pub fn get_shader_paths(&self) -> Vec<PathBuf> {
+        self.shader_paths.clone()
+    }
+
+    /// On an error the last good pipeline stays in use.
+    pub fn reload(&mut self) -> bool {
+        let shader = match self.builder.preprocess() {
+            Ok(shader) => shader,
+            Err(err) => {
+                log::error!("Keeping previous '{}': {}", self.label, err);
+                return false;
+            }
+        };
+
+        let pipeline = match self.builder.build_shader(&self.label, &shader) {
+            Ok(pipeline) => pipeline,
+            Err(err) => {
+                log::error!("Keeping previous '{}': {}", self.label, err);
+                return false;
+            }
+        };
+
+        self.pipeline = pipeline;
+        self.shader_paths = shader.files;
+        log::info!("Reloaded '{}'", self.label);
+        true
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PipelineId(usize);
+
+/// Every pipeline to recompile when a shader changes.
+#[derive(Default)]
+pub struct ReloadablePipelines {
+    pipelines: Vec<ReloadablePipeline>,
+}
+
+impl ReloadablePipelines {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, pipeline: ReloadablePipeline) -> PipelineId {
+        self.pipelines.push(pipeline);
+        PipelineId(self.pipelines.len() - 1)
+    }
+
+    pub fn replace(&mut self, id: PipelineId, pipeline: ReloadablePipeline) {
+        self.pipelines[id.0] = pipeline;
+    }
+
+    pub fn get(&self, id: PipelineId) -> &wgpu::RenderPipeline {
+        self.pipelines[id.0].get_pipeline()
+    }
+
+    /// Returns true if any pipeline was rebuilt.
+    pub fn reload(&mut self) -> bool {
+        let mut reloaded = false;
+        for pipeline in self.pipelines.iter_mut() {
+            reloaded |= pipeline.reload();
+        }
+        reloaded
+    }
+
+    pub fn get_shader_paths(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self
+            .pipelines
+            .iter()
+            .flat_map(ReloadablePipeline::get_shader_paths)
+            .collect();
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+}
+
+fn get_modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[derive(Clone)]
+pub struct WatchedPaths(Arc<Mutex<Vec<PathBuf>>>);
+
+impl WatchedPaths {
+    pub fn set(&self, paths: Vec<PathBuf>) {
+        *self.0.lock().unwrap() = paths;
+    }
+}
+
+pub struct ShaderWatcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    paths: WatchedPaths,
+}
+
+impl ShaderWatcher {
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        let mut watcher = Self {
+            files: Vec::new(),
+            paths: WatchedPaths(Arc::new(Mutex::new(paths))),
+        };
+        watcher.update_files();
+        watcher
+    }
+
+    pub fn get_paths(&self) -> WatchedPaths {
+        self.paths.clone()
+    }
+
+    /// New paths are watched from their current state.
+    fn update_files(&mut self) {
+        let paths = self.paths.0.lock().unwrap().clone();
+        let mut files = Vec::with_capacity(paths.len());
+        for path in paths {
+            let file = match self.files.iter().position(|(known, _)| *known == path) {
+                Some(index) => self.files.swap_remove(index),
+                None => {
+                    let modified = get_modified(&path);
+                    (path, modified)
+                }
+            };
+            files.push(file);
+        }
+        self.files = files;
+    }
+
+    pub fn poll(&mut self) -> bool {
+        self.update_files();
+        let mut changed = false;
+        for (path, modified) in self.files.iter_mut() {
+            let current = get_modified(path);
+            if current != *modified {
+                *modified = current;
+                changed = true;
+            }
+        }
+
+        changed
+    }
+
+    /// Calls `on_change` after every change until it returns false.
+    pub fn spawn<F>(mut self, mut on_change: F) -> WatchedPaths
+    where
+        F: FnMut() -> bool + Send + 'static,
+    {
+        let paths = self.get_paths();
+        thread::spawn(move || loop {
+            thread::sleep(POLL_INTERVAL);
+            if self.poll() && !on_change() {
+                break;
+            }
+        });
+        paths
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer_backend::test_device::get_test_device;
+
+    const FRAGMENT: &str = r#"
+@fragment
+fn fs_main() -> @location(0) vec4f {
+    return vec4f(1.0);
+}
+"#;
+
+    fn get_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "learn_wgpu-hot_reload-{}-{}",
+            std::process::id(),
+            name
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Sets the modification time explicitly, as file system clocks can be coarse.
+    fn write_file(path: &Path, contents: &str, seconds: u64) {
+        fs::write(path, contents).unwrap();
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(seconds);
+        fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    }
+
+    fn get_shader(vertex_x: &str) -> String {
+        format!(
+            r#"
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4f {{
+    return vec4f({vertex_x}, 0.0, 0.0, 1.0);
+}}
+{FRAGMENT}"#
+        )
+    }
+
+    fn build(path: &Path, label: &str) -> ReloadablePipeline {
+        let mut builder = RenderPipelineBuilder::new(&get_test_device().0);
+        builder.set_shader_module(path.to_str().unwrap(), "vs_main", "fs_main");
+        builder.build_reloadable(label).unwrap()
+    }
+
+    #[test]
+    fn watcher_follows_updated_paths() {
+        let dir = get_dir("watcher_follows_updated_paths");
+        let (first, second) = (dir.join("first.wgsl"), dir.join("second.wgsl"));
+        write_file(&first, "", 1);
+        write_file(&second, "", 1);
+
+        let mut watcher = ShaderWatcher::new(vec![first.clone()]);
+        let paths = watcher.get_paths();
+        assert!(!watcher.poll());
+        write_file(&second, "", 2);
+        assert!(!watcher.poll(), "second.wgsl isn't watched yet");
+
+        paths.set(vec![first.clone(), second.clone()]);
+        assert!(!watcher.poll());
+        write_file(&second, "", 3);
+        assert!(watcher.poll());
+        assert!(!watcher.poll());
+
+        paths.set(vec![second]);
+        write_file(&first, "", 2);
+        assert!(!watcher.poll(), "first.wgsl isn't watched anymore");
+    }
+
+    #[test]
+    fn reload_rebuilds_every_pipeline() {
+        let dir = get_dir("reload_rebuilds_every_pipeline");
+        let (first, second) = (dir.join("first.wgsl"), dir.join("second.wgsl"));
+        write_file(&first, &get_shader("0.0"), 1);
+        write_file(&second, &get_shader("1.0"), 1);
+
+        let mut pipelines = ReloadablePipelines::new();
+        let first_id = pipelines.add(build(&first, "First"));
+        let second_id = pipelines.add(build(&second, "Second"));
+        let built = [
+            pipelines.get(first_id).clone(),
+            pipelines.get(second_id).clone(),
+        ];
+        assert_eq!(
+            pipelines.get_shader_paths(),
+            [first.clone(), second.clone()]
+        );
+
+        write_file(&first, "fn vs_main(", 2);
+        write_file(&dir.join("fragment.wgsl"), FRAGMENT, 2);
+        let source = get_shader("1.0").replace(FRAGMENT, "#include \"fragment.wgsl\"\n");
+        write_file(&second, &source, 2);
+        assert!(pipelines.reload());
+        assert_eq!(*pipelines.get(first_id), built[0]);
+        assert_ne!(*pipelines.get(second_id), built[1]);
+        assert_eq!(
+            pipelines.get_shader_paths(),
+            [first.clone(), dir.join("fragment.wgsl"), second]
+        );
+
+        write_file(&dir.join("fragment.wgsl"), "fn fs_main(", 3);
+        assert!(!pipelines.reload());
+    }
+}
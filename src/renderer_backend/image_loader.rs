@@ -0,0 +1,167 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use image::RgbaImage;
+
+use super::material::{LoadOptions, PreparedImage};
+
+pub struct LoadedImage {
+    pub path: PathBuf,
+    pub image: image::ImageResult<PreparedImage>,
+}
+
+/// Decodes and prepares images on a worker thread, in the order they were asked for.
+pub struct ImageLoader {
+    requests: Sender<(PathBuf, LoadOptions)>,
+    loaded: Receiver<LoadedImage>,
+}
+
+impl ImageLoader {
+    /// Calls `on_loaded` after every image until it returns false.
+    pub fn spawn<F>(device: &wgpu::Device, mut on_loaded: F) -> Self
+    where
+        F: FnMut() -> bool + Send + 'static,
+    {
+        let (requests, pending) = mpsc::channel::<(PathBuf, LoadOptions)>();
+        let (done, loaded) = mpsc::channel();
+        let device = device.clone();
+        thread::spawn(move || {
+            for (path, options) in pending {
+                let image = decode(&path).map(|image| PreparedImage::new(image, &options, &device));
+                if done.send(LoadedImage { path, image }).is_err() || !on_loaded() {
+                    break;
+                }
+            }
+        });
+
+        Self { requests, loaded }
+    }
+
+    pub fn load(&self, path: &Path, options: &LoadOptions) {
+        if self.requests.send((path.to_path_buf(), *options)).is_err() {
+            log::error!("Can't load {}: the loader has stopped", path.display());
+        }
+    }
+
+    pub fn get_loaded(&self) -> Vec<LoadedImage> {
+        self.loaded.try_iter().collect()
+    }
+}
+
+pub fn decode(path: &Path) -> image::ImageResult<RgbaImage> {
+    let image = image::ImageReader::open(path)?
+        .with_guessed_format()?
+        .decode()?;
+    Ok(image.to_rgba8())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::Receiver;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::renderer_backend::test_device::get_test_device;
+
+    fn get_test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "learn_wgpu-image_loader-{}-{}",
+            std::process::id(),
+            name
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_image(dir: &Path, name: &str, width: u32, height: u32) -> PathBuf {
+        let path = dir.join(name);
+        RgbaImage::new(width, height).save(&path).unwrap();
+        path
+    }
+
+    fn spawn_loader() -> (ImageLoader, Receiver<()>) {
+        let (signal, signals) = mpsc::channel();
+        let loader = ImageLoader::spawn(&get_test_device().0, move || signal.send(()).is_ok());
+        (loader, signals)
+    }
+
+    fn wait_for(loader: &ImageLoader, signals: &Receiver<()>, count: usize) -> Vec<LoadedImage> {
+        for _ in 0..count {
+            signals
+                .recv_timeout(Duration::from_secs(30))
+                .expect("The loader didn't finish in time");
+        }
+        loader.get_loaded()
+    }
+
+    #[test]
+    fn loads_in_request_order() {
+        let dir = get_test_dir("order");
+        let paths = [
+            write_image(&dir, "wide.png", 7, 1),
+            write_image(&dir, "tall.png", 1, 5),
+            write_image(&dir, "square.png", 3, 3),
+        ];
+
+        let (loader, signals) = spawn_loader();
+        for path in &paths {
+            loader.load(path, &LoadOptions::default());
+        }
+        let loaded = wait_for(&loader, &signals, paths.len());
+
+        let loaded_paths: Vec<&Path> = loaded.iter().map(|image| image.path.as_path()).collect();
+        assert_eq!(
+            loaded_paths,
+            paths.iter().map(PathBuf::as_path).collect::<Vec<_>>()
+        );
+        let sizes: Vec<(u32, u32)> = loaded
+            .iter()
+            .map(|image| image.image.as_ref().unwrap().get_size())
+            .collect();
+        assert_eq!(sizes, [(7, 1), (1, 5), (3, 3)]);
+        assert!(loader.get_loaded().is_empty());
+    }
+
+    #[test]
+    fn keeps_the_options() {
+        let dir = get_test_dir("options");
+        let path = write_image(&dir, "image.png", 2, 2);
+        let options = LoadOptions {
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            mipmaps: false,
+            ..Default::default()
+        };
+
+        let (loader, signals) = spawn_loader();
+        loader.load(&path, &options);
+        let loaded = wait_for(&loader, &signals, 1);
+
+        assert_eq!(loaded[0].image.as_ref().unwrap().get_options(), &options);
+    }
+
+    #[test]
+    fn reports_decode_errors_and_carries_on() {
+        let dir = get_test_dir("errors");
+        let missing = dir.join("missing.png");
+        let garbage = dir.join("garbage.png");
+        std::fs::write(&garbage, b"not an image").unwrap();
+        let valid = write_image(&dir, "valid.png", 2, 2);
+
+        let (loader, signals) = spawn_loader();
+        for path in [&missing, &garbage, &valid] {
+            loader.load(path, &LoadOptions::default());
+        }
+        let loaded = wait_for(&loader, &signals, 3);
+
+        assert_eq!(loaded[0].path, missing);
+        assert!(matches!(
+            loaded[0].image,
+            Err(image::ImageError::IoError(_))
+        ));
+        assert_eq!(loaded[1].path, garbage);
+        assert!(loaded[1].image.is_err());
+        assert_eq!(loaded[2].path, valid);
+        assert!(loaded[2].image.is_ok());
+    }
+}
@@ -1,44 +1,71 @@
-use std::env::current_dir;
-use std::fs;
+use std::path::Path;
+use std::sync::Arc;
 
 use image::RgbaImage;
+use wgpu::util::DeviceExt;
 use wgpu::{Origin3d, TextureAspect};
 
-use super::bind_group;
+use super::bind_group::{self, MaterialResources};
+use super::bind_group_layout::BindGroupLayout;
+use super::mipmap;
+use super::resource_cache::ResourceCache;
 
-const TILE_SIZE: usize = 1024;
+pub const TILE_SIZE: u32 = 1024;
+pub const PLACEHOLDER_COLOR: [u8; 4] = [128, 128, 128, 255];
+
+/// Part of the key `ResourceCache` shares textures under.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct LoadOptions {
+    pub format: wgpu::TextureFormat,
+    pub mipmaps: bool,
+    pub address_mode: wgpu::AddressMode,
+    pub filter: wgpu::FilterMode,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            mipmaps: true,
+            address_mode: wgpu::AddressMode::Repeat,
+            filter: wgpu::FilterMode::Linear,
+        }
+    }
+}
+
+/// Images over the device's size limit are split into `TILE_SIZE` tiles, one
+/// per layer of a 2D array.
+pub struct MaterialTexture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+    pub tile_grid: wgpu::Buffer,
+}
 
 pub struct Material {
+    pub image: Arc<MaterialTexture>,
     pub bind_group: wgpu::BindGroup,
 }
 
 impl Material {
-    fn tile_image(image_bytes: &[u8], tiling: bool) -> Vec<(u32, u32, RgbaImage)> {
-        let loaded_image =
-            image::load_from_memory(&image_bytes).expect("Could not Read Image from bytes");
-        let converted = loaded_image.to_rgba8();
-        let (width, height) = converted.dimensions();
-        let tile_size = TILE_SIZE as u32;
-        let mut tiles: Vec<(u32, u32, RgbaImage)> = vec![];
+    pub fn is_tiled(&self) -> bool {
+        self.image.is_tiled()
+    }
 
-        if !tiling {
-            let tile_width = tile_size.min(width);
-            let tile_height = tile_size.min(height);
-            let tile =
-                image::imageops::crop_imm(&converted, 0, 0, tile_width, tile_height).to_image();
-            tiles.push((0, 0, tile));
-            return tiles;
-        }
+    /// Row by row, each with the position of its top left corner.
+    pub fn tile_image(image: &RgbaImage, tile_size: u32) -> Vec<(u32, u32, RgbaImage)> {
+        let (width, height) = image.dimensions();
+        let mut tiles: Vec<(u32, u32, RgbaImage)> = vec![];
 
         if width <= tile_size && height <= tile_size {
-            tiles.push((0, 0, converted));
+            tiles.push((0, 0, image.clone()));
             return tiles;
         }
 
         for y in (0..height).step_by(tile_size as usize) {
             for x in (0..width).step_by(tile_size as usize) {
                 let tile = image::imageops::crop_imm(
-                    &converted,
+                    image,
                     x,
                     y,
                     (x + tile_size).min(width) - x,
@@ -56,79 +83,382 @@ impl Material {
         filename: &str,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
+        resource_cache: &ResourceCache,
         label: &str,
-        layout: &wgpu::BindGroupLayout,
+        layout: &BindGroupLayout,
     ) -> Self {
-        let mut filepath = current_dir().unwrap();
-        filepath.push(filename);
-        let mut errmsg = String::from("Couldn't Read Image File from File path - ");
-        errmsg.push_str(filepath.as_os_str().to_str().unwrap());
-        let bytes = fs::read(filepath).expect(&errmsg);
+        let image = resource_cache
+            .get_texture(Path::new(filename), &LoadOptions::default(), queue)
+            .unwrap_or_else(|err| panic!("Can't load the image {}! {}", filename, err));
+        Self::from_texture(image, device, resource_cache, label, layout)
+    }
 
-        let tiles = Self::tile_image(&bytes, false);
-        let texture_data = &tiles.get(0).expect("No Tiles available").2;
-        let (width, height) = texture_data.dimensions();
+    pub fn placeholder(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        resource_cache: &ResourceCache,
+        label: &str,
+        layout: &BindGroupLayout,
+    ) -> Self {
+        let image = RgbaImage::from_pixel(1, 1, image::Rgba(PLACEHOLDER_COLOR));
+        Self::from_image(
+            image,
+            &LoadOptions::default(),
+            device,
+            queue,
+            resource_cache,
+            label,
+            layout,
+        )
+    }
 
-        // let loaded_image = image::load_from_memory(&bytes).expect(&errmsg);
-        // let converted = loaded_image.to_rgb8();
-        // let size = loaded_image.dimensions();
+    pub fn from_image(
+        image: RgbaImage,
+        options: &LoadOptions,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        resource_cache: &ResourceCache,
+        label: &str,
+        layout: &BindGroupLayout,
+    ) -> Self {
+        let image = MaterialTexture::new(image, options, device, queue, resource_cache, label);
+        Self::from_texture(Arc::new(image), device, resource_cache, label, layout)
+    }
 
-        let texture_size = wgpu::Extent3d {
-            depth_or_array_layers: 1,
+    pub fn from_texture(
+        image: Arc<MaterialTexture>,
+        device: &wgpu::Device,
+        resource_cache: &ResourceCache,
+        label: &str,
+        layout: &BindGroupLayout,
+    ) -> Self {
+        let MaterialTexture {
+            texture,
+            view,
+            sampler,
+            tile_grid,
+        } = image.as_ref();
+
+        let tiled = image.is_tiled();
+        let dimension = if tiled {
+            wgpu::TextureViewDimension::D2
+        } else {
+            wgpu::TextureViewDimension::D2Array
+        };
+        let (placeholder, placeholder_view) = resource_cache.get_placeholder_texture(dimension);
+        let ((texture, view), (tiles, tiles_view)) = if tiled {
+            ((&placeholder, &placeholder_view), (texture, view))
+        } else {
+            ((texture, view), (&placeholder, &placeholder_view))
+        };
+
+        let mut builder = bind_group::Builder::new(device);
+        builder.set_layout(layout);
+        builder.add_material(
+            0,
+            &MaterialResources {
+                texture,
+                view,
+                sampler,
+                tile_grid,
+                tiles,
+                tiles_view,
+            },
+        );
+        let bind_group = builder
+            .build(label)
+            .unwrap_or_else(|err| panic!("Can't build the material bind group! {}", err));
+
+        Material { image, bind_group }
+    }
+}
+
+/// Everything before the upload, which doesn't touch the GPU.
+pub struct PreparedImage {
+    options: LoadOptions,
+    width: u32,
+    height: u32,
+    /// Only the first mip level of each layer if the GPU renders the rest.
+    layers: Vec<Vec<RgbaImage>>,
+    gpu_mipmaps: bool,
+}
+
+impl PreparedImage {
+    pub fn new(image: RgbaImage, options: &LoadOptions, device: &wgpu::Device) -> Self {
+        let format = options.format;
+        assert!(
+            matches!(
+                format,
+                wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb
+            ),
+            "Images load as Rgba8Unorm or Rgba8UnormSrgb, not {:?}",
+            format
+        );
+
+        let image = Self::fit_to_device(device, image);
+        let (width, height) = image.dimensions();
+
+        let max_size = device.limits().max_texture_dimension_2d;
+        let layers: Vec<RgbaImage> = if width.max(height) <= max_size {
+            vec![image]
+        } else {
+            let tile_size = TILE_SIZE.min(max_size);
+            let mut tiles: Vec<RgbaImage> = Material::tile_image(&image, tile_size)
+                .iter()
+                .map(|(_, _, tile)| Self::pad_tile(tile, tile_size))
+                .collect();
+            // The GL backend makes square textures with a multiple of 6 layers
+            // cube maps, so those get a spare layer.
+            if tiles.len().is_multiple_of(6) {
+                tiles.push(tiles[tiles.len() - 1].clone());
+            }
+            tiles
+        };
+        let tiled = layers.len() > 1;
+
+        // The GL backend can't sample one layer of an array as a 2D texture, so
+        // tiles get their mip levels on the CPU
+        let gpu_mipmaps = options.mipmaps && !tiled && mipmap::is_gpu_supported(device, format);
+        let cpu_mipmaps = options.mipmaps && !gpu_mipmaps;
+        let layers = layers
+            .into_iter()
+            .map(|image| {
+                let mut levels = vec![image];
+                if cpu_mipmaps {
+                    levels.extend(mipmap::generate_cpu(&levels[0], format.is_srgb()));
+                }
+                levels
+            })
+            .collect();
+
+        Self {
+            options: *options,
+            width,
+            height,
+            layers,
+            gpu_mipmaps,
+        }
+    }
+
+    pub fn get_options(&self) -> &LoadOptions {
+        &self.options
+    }
+
+    pub fn get_size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Downscales images that need more tiles than the device has array layers.
+    fn fit_to_device(device: &wgpu::Device, image: RgbaImage) -> RgbaImage {
+        let limits = device.limits();
+        let max_size = limits.max_texture_dimension_2d;
+        let tile_size = TILE_SIZE.min(max_size);
+        let (width, height) = image.dimensions();
+        let tile_count = width.div_ceil(tile_size) * height.div_ceil(tile_size);
+        // Leaves room for the spare layer `new` may add
+        if width.max(height) <= max_size || tile_count < limits.max_texture_array_layers {
+            return image;
+        }
+
+        let scale = max_size as f32 / width.max(height) as f32;
+        let fitted_width = ((width as f32 * scale) as u32).clamp(1, max_size);
+        let fitted_height = ((height as f32 * scale) as u32).clamp(1, max_size);
+        log::warn!(
+            "{}x{} image needs {} tiles but the device allows {} layers; downscaling to {}x{}",
+            width,
+            height,
+            tile_count,
+            limits.max_texture_array_layers,
+            fitted_width,
+            fitted_height
+        );
+        image::imageops::resize(
+            &image,
+            fitted_width,
+            fitted_height,
+            image::imageops::FilterType::Triangle,
+        )
+    }
+
+    /// Repeats the last row and column, so filtering near the edge doesn't pick up black.
+    fn pad_tile(tile: &RgbaImage, size: u32) -> RgbaImage {
+        let (width, height) = tile.dimensions();
+        RgbaImage::from_fn(size, size, |x, y| {
+            *tile.get_pixel(x.min(width - 1), y.min(height - 1))
+        })
+    }
+}
+
+impl MaterialTexture {
+    pub fn new(
+        image: RgbaImage,
+        options: &LoadOptions,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        resource_cache: &ResourceCache,
+        label: &str,
+    ) -> Self {
+        let image = PreparedImage::new(image, options, device);
+        Self::upload(image, device, queue, resource_cache, label)
+    }
+
+    pub fn upload(
+        image: PreparedImage,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        resource_cache: &ResourceCache,
+        label: &str,
+    ) -> Self {
+        let PreparedImage {
+            options,
             width,
             height,
+            layers,
+            gpu_mipmaps,
+        } = image;
+        let format = options.format;
+        let (layer_width, layer_height) = layers[0][0].dimensions();
+        let tiled = layers.len() > 1;
+
+        let texture_size = wgpu::Extent3d {
+            depth_or_array_layers: layers.len() as u32,
+            width: layer_width,
+            height: layer_height,
         };
 
+        let mip_level_count = if options.mipmaps {
+            mipmap::get_mip_level_count(layer_width, layer_height)
+        } else {
+            1
+        };
+        let mut usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST;
+        if gpu_mipmaps {
+            usage |= mipmap::USAGES;
+        }
+
         let texture_descriptor = wgpu::TextureDescriptor {
-            label: Some(filename),
-            mip_level_count: 1,
+            label: Some(label),
+            mip_level_count,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8Unorm,
+            format,
             sample_count: 1,
             size: texture_size,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-            view_formats: &[wgpu::TextureFormat::Rgba8Unorm],
+            usage,
+            view_formats: &[format],
         };
 
         let texture = device.create_texture(&texture_descriptor);
 
-        queue.write_texture(
-            wgpu::TexelCopyTextureInfo {
-                texture: &texture,
-                mip_level: 0,
-                origin: Origin3d::ZERO,
-                aspect: TextureAspect::All,
-            },
-            texture_data,
-            wgpu::TexelCopyBufferLayout {
-                offset: 0,
-                bytes_per_row: Some(width * 4),
-                rows_per_image: Some(height),
-            },
-            texture_size,
-        );
+        for (layer, levels) in layers.iter().enumerate() {
+            for (level, image) in levels.iter().enumerate() {
+                Self::write_level(queue, &texture, layer as u32, level as u32, image);
+            }
+        }
+        if gpu_mipmaps {
+            mipmap::generate(device, queue, resource_cache, &texture);
+        }
+
+        let columns = if tiled {
+            width.div_ceil(layer_width)
+        } else {
+            0
+        };
+        let tile_grid_data: [f32; 4] = [
+            width as f32 / layer_width as f32,
+            height as f32 / layer_height as f32,
+            columns as f32,
+            0.0,
+        ];
+        let tile_grid_descriptor = wgpu::util::BufferInitDescriptor {
+            label: Some("Tile Grid"),
+            contents: bytemuck::cast_slice(&tile_grid_data),
+            usage: wgpu::BufferUsages::UNIFORM,
+        };
+        let tile_grid = device.create_buffer_init(&tile_grid_descriptor);
 
+        let dimension = if tiled {
+            wgpu::TextureViewDimension::D2Array
+        } else {
+            wgpu::TextureViewDimension::D2
+        };
         let view_descriptor = wgpu::TextureViewDescriptor {
+            dimension: Some(dimension),
             ..Default::default()
         };
         let view = texture.create_view(&view_descriptor);
 
+        // Clamped, so tiles don't wrap to their opposite edge and leave seams;
+        // the shader wraps the UV across the whole image
+        let address_mode = if tiled {
+            wgpu::AddressMode::ClampToEdge
+        } else {
+            options.address_mode
+        };
         let sampler_descriptor = wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::Repeat,
-            address_mode_v: wgpu::AddressMode::Repeat,
-            address_mode_w: wgpu::AddressMode::Repeat,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
+            address_mode_u: address_mode,
+            address_mode_v: address_mode,
+            address_mode_w: address_mode,
+            mag_filter: options.filter,
+            min_filter: options.filter,
+            mipmap_filter: options.filter,
             ..Default::default()
         };
-        let sampler = device.create_sampler(&sampler_descriptor);
+        let sampler = resource_cache.get_sampler(&sampler_descriptor);
 
-        let mut builder = bind_group::Builder::new(device);
-        builder.set_layout(layout);
-        builder.add_material(&view, &sampler);
-        let bind_group = builder.build(label);
+        Self {
+            texture,
+            view,
+            sampler,
+            tile_grid,
+        }
+    }
 
-        Material { bind_group }
+    pub fn is_tiled(&self) -> bool {
+        self.texture.depth_or_array_layers() > 1
+    }
+
+    pub fn get_memory_size(&self) -> u64 {
+        let texel_size = self.texture.format().block_copy_size(None).unwrap_or(4) as u64;
+        let size = self.texture.size();
+        (0..self.texture.mip_level_count())
+            .map(|level| {
+                let level_size = size.mip_level_size(level, wgpu::TextureDimension::D2);
+                level_size.width as u64 * level_size.height as u64 * texel_size
+            })
+            .sum::<u64>()
+            * size.depth_or_array_layers as u64
+    }
+
+    fn write_level(
+        queue: &wgpu::Queue,
+        texture: &wgpu::Texture,
+        layer: u32,
+        level: u32,
+        image: &RgbaImage,
+    ) {
+        let (width, height) = image.dimensions();
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture,
+                mip_level: level,
+                origin: Origin3d {
+                    x: 0,
+                    y: 0,
+                    z: layer,
+                },
+                aspect: TextureAspect::All,
+            },
+            image,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(width * 4),
+                rows_per_image: Some(height),
+            },
+            wgpu::Extent3d {
+                depth_or_array_layers: 1,
+                width,
+                height,
+            },
+        );
     }
 }
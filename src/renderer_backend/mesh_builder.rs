@@ -2,6 +2,7 @@ use glm::*;
 use wgpu::util::DeviceExt;
 
 #[repr(C)]
+#[derive(Clone, Copy)]
 pub struct Vertex {
     position: Vec3,
     color: Vec3,
@@ -51,11 +52,10 @@ pub fn make_triangle(device: &wgpu::Device) -> wgpu::Buffer {
         usage: wgpu::BufferUsages::VERTEX,
     };
 
-    let vertex_buffer = device.create_buffer_init(&buffer_descriptor);
-    vertex_buffer
+    device.create_buffer_init(&buffer_descriptor)
 }
 
-pub fn make_quad(device: &wgpu::Device) -> Mesh {
+fn quad_geometry() -> ([Vertex; 4], [u16; 6]) {
     let vertices: [Vertex; 4] = [
         Vertex {
             position: Vec3::new(-0.75, -0.75, 0.0),
@@ -77,6 +77,12 @@ pub fn make_quad(device: &wgpu::Device) -> Mesh {
 
     let indices: [u16; 6] = [0, 1, 2, 2, 3, 0];
 
+    (vertices, indices)
+}
+
+pub fn make_quad(device: &wgpu::Device) -> Mesh {
+    let (vertices, indices) = quad_geometry();
+
     let bytes_vertices = unsafe { any_as_u8_slice(&vertices) };
     let bytes_indices = unsafe { any_as_u8_slice(&indices) };
     let bytes_merged: &[u8] = &[bytes_vertices, bytes_indices].concat();
@@ -94,3 +100,18 @@ pub fn make_quad(device: &wgpu::Device) -> Mesh {
         offset: bytes_vertices.len() as u64,
     }
 }
+
+/// The quad as a plain triangle list, one vertex per corner of every triangle.
+pub fn make_quad_unindexed(device: &wgpu::Device) -> wgpu::Buffer {
+    let (vertices, indices) = quad_geometry();
+    let triangles: [Vertex; 6] = indices.map(|index| vertices[index as usize]);
+
+    let bytes = unsafe { any_as_u8_slice(&triangles) };
+    let buffer_descriptor = wgpu::util::BufferInitDescriptor {
+        label: Some("Quad unindexed vertices buffer"),
+        contents: bytes,
+        usage: wgpu::BufferUsages::VERTEX,
+    };
+
+    device.create_buffer_init(&buffer_descriptor)
+}
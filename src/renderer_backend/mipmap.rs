@@ -0,0 +1,333 @@
+use image::imageops::{self, FilterType};
+use image::{Rgba32FImage, RgbaImage};
+
+use super::bind_group;
+use super::bind_group_layout::{self, BindGroupLayout};
+use super::blend::BlendMode;
+use super::pipeline::RenderPipelineBuilder;
+use super::resource_cache::ResourceCache;
+
+pub fn get_mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+/// The format has to be renderable and filterable.
+pub fn is_gpu_supported(device: &wgpu::Device, format: wgpu::TextureFormat) -> bool {
+    let features = format.guaranteed_format_features(device.features());
+    features
+        .allowed_usages
+        .contains(wgpu::TextureUsages::RENDER_ATTACHMENT)
+        && features
+            .flags
+            .contains(wgpu::TextureFormatFeatureFlags::FILTERABLE)
+}
+
+pub const USAGES: wgpu::TextureUsages = wgpu::TextureUsages::RENDER_ATTACHMENT;
+
+fn get_layout(device: &wgpu::Device, resource_cache: &ResourceCache) -> BindGroupLayout {
+    let mut builder = bind_group_layout::Builder::new(device);
+    builder.add_texture(
+        0,
+        wgpu::ShaderStages::FRAGMENT,
+        wgpu::TextureSampleType::Float { filterable: true },
+        wgpu::TextureViewDimension::D2,
+        false,
+    );
+    builder.add_sampler(
+        1,
+        wgpu::ShaderStages::FRAGMENT,
+        wgpu::SamplerBindingType::Filtering,
+    );
+    builder.build_cached("Mipmap Bind Group Layout", resource_cache)
+}
+
+pub(crate) fn create_pipeline(
+    device: &wgpu::Device,
+    resource_cache: &ResourceCache,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    let layout = get_layout(device, resource_cache);
+
+    let mut builder = RenderPipelineBuilder::new(device);
+    builder.set_shader_module("shaders/mipmap.wgsl", "vs_main", "fs_main");
+    builder.set_pixel_format(format);
+    builder.set_blend_mode(BlendMode::Opaque);
+    builder.set_cull_mode(None);
+    builder.add_bind_group_layout(&layout);
+    builder
+        .build("Mipmap Pipeline")
+        .unwrap_or_else(|err| panic!("Can't build the mipmap pipeline! {}", err))
+}
+
+/// The texture needs `USAGES` and a format `is_gpu_supported` accepts.
+pub fn generate(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    resource_cache: &ResourceCache,
+    texture: &wgpu::Texture,
+) {
+    let format = texture.format();
+    let pipeline = resource_cache.get_mipmap_pipeline(format);
+    let layout = get_layout(device, resource_cache);
+    let sampler = resource_cache.get_sampler(&wgpu::SamplerDescriptor {
+        label: Some("Mipmap Sampler"),
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
+
+    let views: Vec<wgpu::TextureView> = (0..texture.mip_level_count())
+        .map(|level| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("Mip Level"),
+                base_mip_level: level,
+                mip_level_count: Some(1),
+                ..Default::default()
+            })
+        })
+        .collect();
+
+    let command_encoder_descriptor = wgpu::CommandEncoderDescriptor {
+        label: Some("Mipmap Encoder"),
+    };
+    let mut command_encoder = device.create_command_encoder(&command_encoder_descriptor);
+    for pair in views.windows(2) {
+        let [source, target] = pair else {
+            unreachable!()
+        };
+
+        let bind_group: wgpu::BindGroup;
+        {
+            let mut builder = bind_group::Builder::new(device);
+            builder.set_layout(&layout);
+            builder.add_texture(0, texture, source);
+            builder.add_sampler(1, &sampler);
+            bind_group = builder
+                .build("Mipmap Bind Group")
+                .unwrap_or_else(|err| panic!("Can't build the mipmap bind group! {}", err));
+        }
+
+        let color_attachment = wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                store: wgpu::StoreOp::Store,
+            },
+        };
+        let mut renderpass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Mipmap Renderpass"),
+            color_attachments: &[Some(color_attachment)],
+            ..Default::default()
+        });
+        renderpass.set_pipeline(&pipeline);
+        renderpass.set_bind_group(0, &bind_group, &[]);
+        renderpass.draw(0..3, 0..1);
+    }
+    queue.submit([command_encoder.finish()]);
+}
+
+/// With `srgb` the pixels are filtered as linear values.
+pub fn generate_cpu(image: &RgbaImage, srgb: bool) -> Vec<RgbaImage> {
+    let (width, height) = image.dimensions();
+    let level_count = get_mip_level_count(width, height);
+    let level_size = |level: u32| ((width >> level).max(1), (height >> level).max(1));
+
+    if !srgb {
+        return (1..level_count)
+            .map(|level| {
+                let (w, h) = level_size(level);
+                imageops::resize(image, w, h, FilterType::Triangle)
+            })
+            .collect();
+    }
+
+    let linear = Rgba32FImage::from_fn(width, height, |x, y| {
+        let pixel = image.get_pixel(x, y).0;
+        image::Rgba([
+            srgb_to_linear(pixel[0]),
+            srgb_to_linear(pixel[1]),
+            srgb_to_linear(pixel[2]),
+            pixel[3] as f32 / 255.0,
+        ])
+    });
+    (1..level_count)
+        .map(|level| {
+            let (w, h) = level_size(level);
+            let resized = imageops::resize(&linear, w, h, FilterType::Triangle);
+            RgbaImage::from_fn(w, h, |x, y| {
+                let pixel = resized.get_pixel(x, y).0;
+                image::Rgba([
+                    linear_to_srgb(pixel[0]),
+                    linear_to_srgb(pixel[1]),
+                    linear_to_srgb(pixel[2]),
+                    (pixel[3] * 255.0).round().clamp(0.0, 255.0) as u8,
+                ])
+            })
+        })
+        .collect()
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let value = value.clamp(0.0, 1.0);
+    let encoded = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer_backend::compute::read_buffer;
+    use crate::renderer_backend::test_device::get_test_device;
+
+    fn checkerboard(size: u32) -> RgbaImage {
+        RgbaImage::from_fn(size, size, |x, y| {
+            let value = if (x + y) % 2 == 0 { 0 } else { 255 };
+            image::Rgba([value, value, value, 255])
+        })
+    }
+
+    #[test]
+    fn mip_level_count() {
+        assert_eq!(get_mip_level_count(0, 0), 1);
+        assert_eq!(get_mip_level_count(1, 1), 1);
+        assert_eq!(get_mip_level_count(2, 1), 2);
+        assert_eq!(get_mip_level_count(256, 256), 9);
+        assert_eq!(get_mip_level_count(257, 1), 9);
+        assert_eq!(get_mip_level_count(512, 1), 10);
+        assert_eq!(get_mip_level_count(300, 209), 9);
+        assert_eq!(get_mip_level_count(3, 1024), 11);
+    }
+
+    #[test]
+    fn cpu_level_sizes() {
+        let image = RgbaImage::new(300, 209);
+        let sizes: Vec<(u32, u32)> = generate_cpu(&image, false)
+            .iter()
+            .map(RgbaImage::dimensions)
+            .collect();
+
+        assert_eq!(
+            sizes,
+            [
+                (150, 104),
+                (75, 52),
+                (37, 26),
+                (18, 13),
+                (9, 6),
+                (4, 3),
+                (2, 1),
+                (1, 1)
+            ]
+        );
+    }
+
+    /// 50% grey in linear light is 188 in sRGB.
+    #[test]
+    fn cpu_averages_srgb_on_linear_values() {
+        let image = checkerboard(2);
+
+        let linear = generate_cpu(&image, false);
+        let srgb = generate_cpu(&image, true);
+
+        assert_eq!(linear.len(), 1);
+        assert_eq!(srgb.len(), 1);
+        assert!(linear[0].get_pixel(0, 0)[0].abs_diff(128) <= 1);
+        assert!(srgb[0].get_pixel(0, 0)[0].abs_diff(188) <= 1);
+        assert_eq!(srgb[0].get_pixel(0, 0)[3], 255);
+    }
+
+    fn generate_smallest_level(image: &RgbaImage, format: wgpu::TextureFormat) -> [u8; 4] {
+        let (device, queue) = get_test_device();
+        let resource_cache = ResourceCache::new(device);
+        assert!(is_gpu_supported(device, format));
+
+        let (width, height) = image.dimensions();
+        let mip_level_count = get_mip_level_count(width, height);
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Test Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC
+                | USAGES,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            texture.as_image_copy(),
+            image,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * width),
+                rows_per_image: Some(height),
+            },
+            texture.size(),
+        );
+        generate(device, queue, &resource_cache, &texture);
+
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Test Readback"),
+            size: 4,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut command_encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        command_encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo {
+                texture: &texture,
+                mip_level: mip_level_count - 1,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::TexelCopyBufferInfo {
+                buffer: &readback,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: None,
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit([command_encoder.finish()]);
+
+        read_buffer(device, &readback).try_into().unwrap()
+    }
+
+    #[test]
+    fn gpu_averages_srgb_on_linear_values() {
+        let image = checkerboard(2);
+
+        let linear = generate_smallest_level(&image, wgpu::TextureFormat::Rgba8Unorm);
+        let srgb = generate_smallest_level(&image, wgpu::TextureFormat::Rgba8UnormSrgb);
+
+        assert!(linear[0].abs_diff(128) <= 1, "{linear:?}");
+        assert!(srgb[0].abs_diff(188) <= 1, "{srgb:?}");
+    }
+}
@@ -1,5 +1,23 @@
 pub mod bind_group;
 pub mod bind_group_layout;
+pub mod bindless;
+pub mod blend;
+pub mod compute;
+pub mod depth_texture;
+pub mod hot_reload;
+pub mod image_loader;
 pub mod material;
 pub mod mesh_builder;
+pub mod mipmap;
+pub mod multisample;
+pub mod offscreen;
 pub mod pipeline;
+pub mod pipeline_cache;
+pub mod preprocessor;
+pub mod push_constants;
+pub mod reflection;
+pub mod resource_cache;
+#[cfg(test)]
+mod test_device;
+pub mod variant_cache;
+pub mod viewer;
@@ -0,0 +1,85 @@
+const SAMPLE_COUNTS: [u32; 4] = [8, 4, 2, 1];
+
+/// The highest count up to `requested` that every one of `formats` supports.
+pub fn get_supported_sample_count(
+    adapter: &wgpu::Adapter,
+    device: &wgpu::Device,
+    formats: &[wgpu::TextureFormat],
+    requested: u32,
+) -> u32 {
+    let supported = |count: u32| {
+        formats
+            .iter()
+            .all(|&format| is_sample_count_supported(Some(adapter), device, format, count))
+    };
+
+    let sample_count = SAMPLE_COUNTS
+        .into_iter()
+        .filter(|&count| count <= requested)
+        .find(|&count| supported(count))
+        .unwrap_or(1);
+    if sample_count != requested {
+        log::warn!(
+            "{}x MSAA isn't supported for {:?}, using {}x",
+            requested,
+            formats,
+            sample_count
+        );
+    }
+
+    sample_count
+}
+
+/// Without `TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES` or an adapter, only the
+/// counts guaranteed by WebGPU are supported.
+pub fn is_sample_count_supported(
+    adapter: Option<&wgpu::Adapter>,
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+    count: u32,
+) -> bool {
+    let adapter_specific = device
+        .features()
+        .contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+    let format_features = match adapter {
+        Some(adapter) if adapter_specific => adapter.get_texture_format_features(format),
+        _ => format.guaranteed_format_features(device.features()),
+    };
+    format_features.flags.sample_count_supported(count)
+}
+
+pub struct MultisampledTexture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+}
+
+impl MultisampledTexture {
+    pub fn new(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        let texture_size = wgpu::Extent3d {
+            depth_or_array_layers: 1,
+            width,
+            height,
+        };
+
+        let texture_descriptor = wgpu::TextureDescriptor {
+            label: Some("Multisampled Color Target"),
+            mip_level_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            sample_count,
+            size: texture_size,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        };
+        let texture = device.create_texture(&texture_descriptor);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        MultisampledTexture { texture, view }
+    }
+}
@@ -0,0 +1,110 @@
+use image::RgbaImage;
+
+use super::compute::read_buffer;
+
+pub struct OffscreenTarget {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    readback: wgpu::Buffer,
+    bytes_per_pixel: u32,
+    padded_bytes_per_row: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl OffscreenTarget {
+    pub fn new(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let texture_size = wgpu::Extent3d {
+            depth_or_array_layers: 1,
+            width,
+            height,
+        };
+
+        let texture_descriptor = wgpu::TextureDescriptor {
+            label: Some("Offscreen Target"),
+            mip_level_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            sample_count: 1,
+            size: texture_size,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        };
+        let texture = device.create_texture(&texture_descriptor);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        // Rows copied out of a texture have to be aligned to 256 bytes.
+        let bytes_per_pixel = format
+            .block_copy_size(None)
+            .expect("Offscreen targets need a color format");
+        let bytes_per_row = width * bytes_per_pixel;
+        let padded_bytes_per_row = bytes_per_row.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let readback_descriptor = wgpu::BufferDescriptor {
+            label: Some("Offscreen Readback"),
+            size: (padded_bytes_per_row * height) as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        };
+        let readback = device.create_buffer(&readback_descriptor);
+
+        OffscreenTarget {
+            texture,
+            view,
+            readback,
+            bytes_per_pixel,
+            padded_bytes_per_row,
+            width,
+            height,
+        }
+    }
+
+    /// Copies the target back to the CPU as tightly packed rows, in the texture's
+    /// own format.
+    pub fn read_bytes(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<u8> {
+        let command_encoder_descriptor = wgpu::CommandEncoderDescriptor {
+            label: Some("Readback Encoder"),
+        };
+        let mut command_encoder = device.create_command_encoder(&command_encoder_descriptor);
+        command_encoder.copy_texture_to_buffer(
+            self.texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &self.readback,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(self.padded_bytes_per_row),
+                    rows_per_image: Some(self.height),
+                },
+            },
+            self.texture.size(),
+        );
+        queue.submit([command_encoder.finish()]);
+
+        let padded = read_buffer(device, &self.readback);
+        let bytes_per_row = (self.width * self.bytes_per_pixel) as usize;
+        padded
+            .chunks(self.padded_bytes_per_row as usize)
+            .flat_map(|row| &row[..bytes_per_row])
+            .copied()
+            .collect()
+    }
+
+    /// Copies an 8-bit RGBA target back to the CPU.
+    pub fn read(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> RgbaImage {
+        let pixels = self.read_bytes(device, queue);
+        RgbaImage::from_raw(self.width, self.height, pixels).expect("Readback size mismatch")
+    }
+
+    pub fn save_png(&self, device: &wgpu::Device, queue: &wgpu::Queue, path: &str) {
+        let image = self.read(device, queue);
+        image
+            .save_with_format(path, image::ImageFormat::Png)
+            .expect("Couldn't write PNG file");
+    }
+}
@@ -1,44 +1,271 @@
 use std::env::current_dir;
-use std::fs;
+use std::fmt;
+use std::path::PathBuf;
 
-pub struct RenderPipelineBuilder<'a> {
+use futures::executor::block_on;
+use wgpu::naga;
+
+use super::bind_group_layout::{self, BindGroupLayout};
+use super::bindless::BindlessMaterials;
+use super::blend::BlendMode;
+use super::hot_reload::ReloadablePipeline;
+use super::multisample;
+use super::preprocessor::{PreprocessError, Preprocessor, ProcessedShader};
+use super::push_constants::PushConstants;
+use super::reflection::{self, ReflectionError, ShaderReflection};
+use super::variant_cache::{VariantCache, VariantKey};
+
+pub fn shader_path(shader_filename: &str) -> PathBuf {
+    let mut filepath = current_dir().unwrap();
+    filepath.push("src");
+    filepath.push(shader_filename);
+    filepath
+}
+
+fn load_shader_source(shader_filename: &str, preprocessor: &Preprocessor) -> ProcessedShader {
+    preprocessor
+        .process(&shader_path(shader_filename))
+        .unwrap_or_else(|err| panic!("Can't read shader source code! {}", err))
+}
+
+fn create_shader_module(device: &wgpu::Device, source_code: &str) -> wgpu::ShaderModule {
+    let shader_module_descriptor = wgpu::ShaderModuleDescriptor {
+        label: Some("Shader Module"),
+        source: wgpu::ShaderSource::Wgsl(source_code.into()),
+    };
+    device.create_shader_module(shader_module_descriptor)
+}
+
+fn load_shader_module(device: &wgpu::Device, shader_filename: &str) -> wgpu::ShaderModule {
+    let shader = load_shader_source(shader_filename, &Preprocessor::new());
+    create_shader_module(device, &shader.source)
+}
+
+#[derive(Debug)]
+pub enum PipelineError {
+    MissingFile(PreprocessError),
+    Preprocess(PreprocessError),
+    Parse(String),
+    /// The shader doesn't match the layouts added to the builder.
+    LayoutMismatch(ReflectionError),
+    Validation(wgpu::Error),
+    UnsupportedSampleCount {
+        format: wgpu::TextureFormat,
+        count: u32,
+    },
+    MissingFeatures(wgpu::Features),
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineError::MissingFile(err) | PipelineError::Preprocess(err) => {
+                write!(f, "{}", err)
+            }
+            PipelineError::Parse(message) => write!(f, "{}", message),
+            PipelineError::LayoutMismatch(err) => write!(f, "{}", err),
+            PipelineError::Validation(err) => write!(f, "{}", err),
+            PipelineError::UnsupportedSampleCount { format, count } => {
+                write!(f, "{:?} doesn't support {} samples", format, count)
+            }
+            PipelineError::MissingFeatures(features) => {
+                write!(f, "the device lacks {:?}", features)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PipelineError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PipelineError::MissingFile(err) | PipelineError::Preprocess(err) => Some(err),
+            PipelineError::Parse(_) => None,
+            PipelineError::LayoutMismatch(err) => Some(err),
+            PipelineError::Validation(err) => Some(err),
+            PipelineError::UnsupportedSampleCount { .. } | PipelineError::MissingFeatures(_) => {
+                None
+            }
+        }
+    }
+}
+
+impl From<PreprocessError> for PipelineError {
+    fn from(err: PreprocessError) -> Self {
+        if err.missing.is_some() {
+            PipelineError::MissingFile(err)
+        } else {
+            PipelineError::Preprocess(err)
+        }
+    }
+}
+
+impl From<ReflectionError> for PipelineError {
+    fn from(err: ReflectionError) -> Self {
+        match err {
+            ReflectionError::Shader(message) => PipelineError::Parse(message),
+            err => PipelineError::LayoutMismatch(err),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct ColorTarget {
+    format: wgpu::TextureFormat,
+    blend_mode: BlendMode,
+    write_mask: wgpu::ColorWrites,
+}
+
+/// Holds owned handles so it can be kept to rebuild the pipeline later.
+#[derive(Clone)]
+pub struct RenderPipelineBuilder {
     shader_filename: String,
     vertex_entry: String,
     fragment_entry: String,
-    pixel_format: wgpu::TextureFormat,
-    bind_group_layouts: Vec<&'a wgpu::BindGroupLayout>,
+    /// `set_pixel_format`, `set_blend_mode` and `set_write_mask` configure the first.
+    color_targets: Vec<ColorTarget>,
+    primitive: wgpu::PrimitiveState,
+    depth_stencil: Option<wgpu::DepthStencilState>,
+    multisample: wgpu::MultisampleState,
+    bind_group_layouts: Vec<BindGroupLayout>,
     vertex_buffer_layouts: Vec<wgpu::VertexBufferLayout<'static>>,
-    device: Option<&'a wgpu::Device>,
+    constants: Vec<(String, f64)>,
+    push_constant_ranges: Vec<wgpu::PushConstantRange>,
+    push_constant_fallback: Option<(u32, BindGroupLayout)>,
+    pipeline_cache: Option<wgpu::PipelineCache>,
+    preprocessor: Preprocessor,
+    adapter: Option<wgpu::Adapter>,
+    device: wgpu::Device,
 }
 
-impl<'a> RenderPipelineBuilder<'a> {
-    pub fn new(device: &'a wgpu::Device) -> Self {
-        return Self {
+impl RenderPipelineBuilder {
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
             shader_filename: "".to_string(),
             vertex_entry: "".to_string(),
             fragment_entry: "".to_string(),
-            pixel_format: wgpu::TextureFormat::Rgba8Unorm,
+            color_targets: vec![ColorTarget {
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                blend_mode: BlendMode::Opaque,
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
             vertex_buffer_layouts: Vec::new(),
             bind_group_layouts: Vec::new(),
-            device: Some(device),
-        };
+            constants: Vec::new(),
+            push_constant_ranges: Vec::new(),
+            push_constant_fallback: None,
+            pipeline_cache: None,
+            preprocessor: Preprocessor::new(),
+            adapter: None,
+            device: device.clone(),
+        }
     }
 
-    fn get_device(&self) -> &wgpu::Device {
-        self.device.as_ref().unwrap()
+    pub(crate) fn get_device(&self) -> &wgpu::Device {
+        &self.device
+    }
+
+    pub fn get_shader_filename(&self) -> &str {
+        &self.shader_filename
     }
 
     pub fn reset(&mut self) {
         self.vertex_buffer_layouts.clear();
         self.bind_group_layouts.clear();
+        self.color_targets.truncate(1);
+        self.constants.clear();
+        self.push_constant_ranges.clear();
+        self.push_constant_fallback = None;
+        self.preprocessor = Preprocessor::new();
+    }
+
+    pub fn add_define(&mut self, name: &str, value: &str) {
+        self.preprocessor.define(name, value);
+    }
+
+    pub fn add_feature(&mut self, key: &str) {
+        self.preprocessor.define(key, "");
     }
 
     pub fn add_vertex_buffer_layout(&mut self, layout: wgpu::VertexBufferLayout<'static>) {
         self.vertex_buffer_layouts.push(layout);
     }
 
-    pub fn add_bind_group_layout(&mut self, layout: &'a wgpu::BindGroupLayout) {
-        self.bind_group_layouts.push(layout);
+    pub fn add_bind_group_layout(&mut self, layout: &BindGroupLayout) {
+        self.bind_group_layouts.push(layout.clone());
+    }
+
+    /// Not part of the variant key, as the cache doesn't change the result.
+    pub fn set_pipeline_cache(&mut self, cache: &wgpu::PipelineCache) {
+        self.pipeline_cache = Some(cache.clone());
+    }
+
+    /// By name or by `@id`.
+    pub fn set_constant(&mut self, name: &str, value: f64) {
+        self.constants.retain(|(constant, _)| constant != name);
+        self.constants.push((name.to_string(), value));
+    }
+
+    /// Defines `PUSH_CONSTANTS` when they are native, or adds the uniform fallback's layout.
+    pub fn set_push_constants(&mut self, push_constants: &PushConstants) {
+        match push_constants.get_bind_group_layout() {
+            None => {
+                self.push_constant_ranges = vec![push_constants.get_range()];
+                self.push_constant_fallback = None;
+                self.add_feature("PUSH_CONSTANTS");
+            }
+            Some(layout) => {
+                self.push_constant_ranges.clear();
+                self.push_constant_fallback = Some((push_constants.get_group(), layout.clone()));
+            }
+        }
+    }
+
+    /// Call it after adding the mesh's vertex buffer layout.
+    pub fn set_bindless_materials(&mut self, materials: &BindlessMaterials) {
+        self.add_vertex_buffer_layout(BindlessMaterials::get_instance_layout());
+        self.add_feature("BINDLESS");
+        self.add_define("MATERIAL_COUNT", &materials.get_count().to_string());
+    }
+
+    /// Groups the shader skips get an empty layout.
+    pub fn add_reflected_bind_group_layouts(
+        &mut self,
+        label: &str,
+    ) -> Result<Vec<BindGroupLayout>, ReflectionError> {
+        let reflection = self.reflect()?;
+        let group_count = reflection
+            .bind_groups
+            .keys()
+            .next_back()
+            .map_or(0, |group| group + 1);
+
+        let mut layouts = Vec::new();
+        for group in self.get_bind_group_layouts().len() as u32..group_count {
+            let mut builder = bind_group_layout::Builder::new(&self.device);
+            for entry in reflection.bind_groups.get(&group).into_iter().flatten() {
+                builder.add_entry(*entry);
+            }
+            let layout = builder.build(&format!("{} Group {}", label, group));
+            self.bind_group_layouts.push(layout.clone());
+            layouts.push(layout);
+        }
+
+        Ok(layouts)
     }
 
     pub fn set_shader_module(
@@ -53,38 +280,328 @@ impl<'a> RenderPipelineBuilder<'a> {
     }
 
     pub fn set_pixel_format(&mut self, pixel_format: wgpu::TextureFormat) {
-        self.pixel_format = pixel_format;
+        self.color_targets[0].format = pixel_format;
+    }
+
+    pub fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.color_targets[0].blend_mode = blend_mode;
+    }
+
+    pub fn set_write_mask(&mut self, write_mask: wgpu::ColorWrites) {
+        self.color_targets[0].write_mask = write_mask;
+    }
+
+    /// Integer formats need `BlendMode::Opaque`.
+    pub fn add_color_target(
+        &mut self,
+        format: wgpu::TextureFormat,
+        blend_mode: BlendMode,
+        write_mask: wgpu::ColorWrites,
+    ) {
+        self.color_targets.push(ColorTarget {
+            format,
+            blend_mode,
+            write_mask,
+        });
+    }
+
+    pub fn set_topology(&mut self, topology: wgpu::PrimitiveTopology) {
+        self.primitive.topology = topology;
+    }
+
+    /// Required for indexed strip draws.
+    pub fn set_strip_index_format(&mut self, strip_index_format: Option<wgpu::IndexFormat>) {
+        self.primitive.strip_index_format = strip_index_format;
+    }
+
+    pub fn set_front_face(&mut self, front_face: wgpu::FrontFace) {
+        self.primitive.front_face = front_face;
+    }
+
+    pub fn set_cull_mode(&mut self, cull_mode: Option<wgpu::Face>) {
+        self.primitive.cull_mode = cull_mode;
     }
 
-    pub fn build(&mut self, label: &str) -> wgpu::RenderPipeline {
-        let mut filepath = current_dir().unwrap();
-        filepath.push("src");
-        filepath.push(self.shader_filename.as_str());
+    /// `Line` and `Point` need their `POLYGON_MODE_*` feature.
+    pub fn set_polygon_mode(&mut self, polygon_mode: wgpu::PolygonMode) {
+        self.primitive.polygon_mode = polygon_mode;
+    }
+
+    pub fn set_depth_stencil(
+        &mut self,
+        format: wgpu::TextureFormat,
+        depth_compare: wgpu::CompareFunction,
+        depth_write_enabled: bool,
+    ) {
+        self.depth_stencil = Some(wgpu::DepthStencilState {
+            format,
+            depth_write_enabled,
+            depth_compare,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        });
+    }
+
+    /// See `multisample::get_supported_sample_count`.
+    pub fn set_sample_count(&mut self, sample_count: u32) {
+        self.multisample.count = sample_count;
+    }
+
+    /// Only needed with `TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES`.
+    pub fn set_adapter(&mut self, adapter: &wgpu::Adapter) {
+        self.adapter = Some(adapter.clone());
+    }
+
+    pub fn set_alpha_to_coverage(&mut self, enabled: bool) {
+        self.multisample.alpha_to_coverage_enabled = enabled;
+    }
+
+    /// Call `set_depth_stencil` first.
+    pub fn set_stencil(&mut self, stencil: wgpu::StencilState) {
+        self.depth_stencil
+            .as_mut()
+            .expect("set_depth_stencil must be called before set_stencil")
+            .stencil = stencil;
+    }
+
+    pub fn reflect(&self) -> Result<ShaderReflection, ReflectionError> {
+        let shader = self.preprocess()?;
+        self.reflect_shader(&shader)
+    }
+
+    pub fn validate(&self) -> Result<(), ReflectionError> {
+        let reflection = self.reflect()?;
+        self.check_layouts(&reflection)
+    }
+
+    pub fn build(&mut self, label: &str) -> Result<wgpu::RenderPipeline, PipelineError> {
+        let result = self
+            .preprocess()
+            .map_err(PipelineError::from)
+            .and_then(|shader| self.build_shader(label, &shader));
+        self.reset();
+
+        result
+    }
 
-        let source_code = fs::read_to_string(filepath).expect("Can't read shader source code!");
+    pub fn build_reloadable(&mut self, label: &str) -> Result<ReloadablePipeline, PipelineError> {
+        let builder = self.clone();
+        let result = self
+            .preprocess()
+            .map_err(PipelineError::from)
+            .and_then(|shader| {
+                let render_pipeline = self.build_shader(label, &shader)?;
+                Ok(ReloadablePipeline::new(
+                    render_pipeline,
+                    builder,
+                    label,
+                    shader.files,
+                ))
+            });
+        self.reset();
+
+        result
+    }
 
-        let shader_module_descriptor = wgpu::ShaderModuleDescriptor {
-            label: Some("Shader Module"),
-            source: wgpu::ShaderSource::Wgsl(source_code.into()),
+    pub fn build_variant(
+        &mut self,
+        label: &str,
+        cache: &mut VariantCache,
+    ) -> Result<wgpu::RenderPipeline, PipelineError> {
+        let key = self.get_variant_key();
+        if let Some(render_pipeline) = cache.get(&key) {
+            self.reset();
+            return Ok(render_pipeline.clone());
+        }
+
+        let render_pipeline = self.build(label)?;
+        cache.insert(key, render_pipeline.clone());
+
+        Ok(render_pipeline)
+    }
+
+    fn get_variant_key(&self) -> VariantKey {
+        VariantKey {
+            shader_filename: self.shader_filename.clone(),
+            vertex_entry: self.vertex_entry.clone(),
+            fragment_entry: self.fragment_entry.clone(),
+            defines: self.preprocessor.get_defines(),
+            vertex_buffer_layouts: self
+                .vertex_buffer_layouts
+                .iter()
+                .map(|layout| {
+                    (
+                        layout.array_stride,
+                        layout.step_mode,
+                        layout.attributes.to_vec(),
+                    )
+                })
+                .collect(),
+            bind_group_layouts: self
+                .get_bind_group_layouts()
+                .into_iter()
+                .map(|layout| layout.layout.clone())
+                .collect(),
+            constants: self
+                .constants
+                .iter()
+                .map(|(name, value)| (name.clone(), value.to_bits()))
+                .collect(),
+            push_constant_ranges: self.push_constant_ranges.clone(),
+            color_targets: self.color_targets.clone(),
+            primitive: self.primitive,
+            depth_stencil: self.depth_stencil.clone(),
+            multisample: self.multisample,
+        }
+    }
+
+    pub(crate) fn preprocess(&self) -> Result<ProcessedShader, PreprocessError> {
+        self.preprocessor
+            .process(&shader_path(&self.shader_filename))
+    }
+
+    pub(crate) fn reflect_shader(
+        &self,
+        shader: &ProcessedShader,
+    ) -> Result<ShaderReflection, ReflectionError> {
+        reflection::reflect(
+            shader,
+            &[
+                (&self.vertex_entry, naga::ShaderStage::Vertex),
+                (&self.fragment_entry, naga::ShaderStage::Fragment),
+            ],
+        )
+    }
+
+    fn get_bind_group_layouts(&self) -> Vec<&BindGroupLayout> {
+        let mut layouts: Vec<&BindGroupLayout> = self.bind_group_layouts.iter().collect();
+        if let Some((group, layout)) = &self.push_constant_fallback {
+            layouts.insert((*group as usize).min(layouts.len()), layout);
+        }
+        layouts
+    }
+
+    pub(crate) fn check_layouts(
+        &self,
+        reflection: &ShaderReflection,
+    ) -> Result<(), ReflectionError> {
+        for (name, _) in &self.constants {
+            if !reflection.constants.contains(name) {
+                return Err(ReflectionError::MissingConstant { name: name.clone() });
+            }
+        }
+        reflection.check_vertex_buffer_layouts(&self.vertex_buffer_layouts)?;
+        let formats: Vec<wgpu::TextureFormat> = self
+            .color_targets
+            .iter()
+            .map(|target| target.format)
+            .collect();
+        reflection.check_color_targets(&formats)?;
+        let bind_group_layouts = self.get_bind_group_layouts();
+        for &group in reflection.bind_groups.keys() {
+            let layout = bind_group_layouts
+                .get(group as usize)
+                .ok_or(ReflectionError::MissingBindGroup { group })?;
+            reflection.check_bind_group_layout(group, &layout.entries)?;
+        }
+
+        Ok(())
+    }
+
+    fn check_polygon_mode(&self) -> Result<(), PipelineError> {
+        let required = match self.primitive.polygon_mode {
+            wgpu::PolygonMode::Fill => wgpu::Features::empty(),
+            wgpu::PolygonMode::Line => wgpu::Features::POLYGON_MODE_LINE,
+            wgpu::PolygonMode::Point => wgpu::Features::POLYGON_MODE_POINT,
         };
-        let shader_module = self
-            .get_device()
-            .create_shader_module(shader_module_descriptor);
+        let missing = required - self.device.features();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(PipelineError::MissingFeatures(missing))
+        }
+    }
 
+    fn check_sample_count(&self) -> Result<(), PipelineError> {
+        let count = self.multisample.count;
+        let depth_format = self.depth_stencil.as_ref().map(|state| state.format);
+        let formats = self
+            .color_targets
+            .iter()
+            .map(|target| target.format)
+            .chain(depth_format);
+        for format in formats {
+            if !multisample::is_sample_count_supported(
+                self.adapter.as_ref(),
+                &self.device,
+                format,
+                count,
+            ) {
+                return Err(PipelineError::UnsupportedSampleCount { format, count });
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn build_shader(
+        &self,
+        label: &str,
+        shader: &ProcessedShader,
+    ) -> Result<wgpu::RenderPipeline, PipelineError> {
+        let reflection = self.reflect_shader(shader)?;
+        self.check_layouts(&reflection)?;
+        self.check_sample_count()?;
+        self.check_polygon_mode()?;
+
+        let device = self.get_device();
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let render_pipeline = self.build_from_source(label, &shader.source);
+        if let Some(err) = block_on(device.pop_error_scope()) {
+            return Err(PipelineError::Validation(err));
+        }
+
+        Ok(render_pipeline)
+    }
+
+    fn build_from_source(&self, label: &str, source_code: &str) -> wgpu::RenderPipeline {
+        let shader_module = create_shader_module(self.get_device(), source_code);
+
+        let bind_group_layouts: Vec<&wgpu::BindGroupLayout> = self
+            .get_bind_group_layouts()
+            .into_iter()
+            .map(|layout| &layout.layout)
+            .collect();
         let pipeline_layout_descriptor = wgpu::PipelineLayoutDescriptor {
             label: Some(label),
-            bind_group_layouts: &self.bind_group_layouts,
-            push_constant_ranges: &[],
+            bind_group_layouts: &bind_group_layouts,
+            push_constant_ranges: &self.push_constant_ranges,
         };
         let pipeline_layout = self
             .get_device()
             .create_pipeline_layout(&pipeline_layout_descriptor);
 
-        let render_targets = [Some(wgpu::ColorTargetState {
-            format: self.pixel_format,
-            blend: Some(wgpu::BlendState::REPLACE),
-            write_mask: wgpu::ColorWrites::ALL,
-        })];
+        let render_targets: Vec<Option<wgpu::ColorTargetState>> = self
+            .color_targets
+            .iter()
+            .map(|target| {
+                Some(wgpu::ColorTargetState {
+                    format: target.format,
+                    blend: target.blend_mode.get_blend_state(),
+                    write_mask: target.write_mask,
+                })
+            })
+            .collect();
+
+        let constants: Vec<(&str, f64)> = self
+            .constants
+            .iter()
+            .map(|(name, value)| (name.as_str(), *value))
+            .collect();
+        let compilation_options = wgpu::PipelineCompilationOptions {
+            constants: &constants,
+            ..Default::default()
+        };
 
         let render_pipeline_descriptor = wgpu::RenderPipelineDescriptor {
             label: Some(label),
@@ -93,38 +610,241 @@ impl<'a> RenderPipelineBuilder<'a> {
                 module: &shader_module,
                 entry_point: Some(&self.vertex_entry),
                 buffers: &self.vertex_buffer_layouts,
-                compilation_options: Default::default(),
+                compilation_options: compilation_options.clone(),
             },
             fragment: Some(wgpu::FragmentState {
                 module: &shader_module,
                 entry_point: Some(&self.fragment_entry),
                 targets: &render_targets,
-                compilation_options: Default::default(),
+                compilation_options,
             }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: 1,
-                alpha_to_coverage_enabled: false,
-            },
+            primitive: self.primitive,
+            depth_stencil: self.depth_stencil.clone(),
+            multisample: self.multisample,
             multiview: None,
-            cache: None,
+            cache: self.pipeline_cache.as_ref(),
         };
-        let render_pipeline: wgpu::RenderPipeline;
-        render_pipeline = self
+        self.get_device()
+            .create_render_pipeline(&render_pipeline_descriptor)
+    }
+}
+
+pub struct ComputePipelineBuilder<'a> {
+    shader_filename: String,
+    entry_point: String,
+    bind_group_layouts: Vec<&'a wgpu::BindGroupLayout>,
+    pipeline_cache: Option<&'a wgpu::PipelineCache>,
+    device: Option<&'a wgpu::Device>,
+}
+
+impl<'a> ComputePipelineBuilder<'a> {
+    pub fn new(device: &'a wgpu::Device) -> Self {
+        Self {
+            shader_filename: "".to_string(),
+            entry_point: "".to_string(),
+            bind_group_layouts: Vec::new(),
+            pipeline_cache: None,
+            device: Some(device),
+        }
+    }
+
+    fn get_device(&self) -> &wgpu::Device {
+        self.device.as_ref().unwrap()
+    }
+
+    pub fn reset(&mut self) {
+        self.bind_group_layouts.clear();
+    }
+
+    pub fn add_bind_group_layout(&mut self, layout: &'a wgpu::BindGroupLayout) {
+        self.bind_group_layouts.push(layout);
+    }
+
+    pub fn set_shader_module(&mut self, shader_filename: &str, entry_point: &str) {
+        self.shader_filename = shader_filename.to_string();
+        self.entry_point = entry_point.to_string();
+    }
+
+    pub fn set_pipeline_cache(&mut self, cache: &'a wgpu::PipelineCache) {
+        self.pipeline_cache = Some(cache);
+    }
+
+    pub fn build(&mut self, label: &str) -> wgpu::ComputePipeline {
+        let shader_module = load_shader_module(self.get_device(), &self.shader_filename);
+
+        let pipeline_layout_descriptor = wgpu::PipelineLayoutDescriptor {
+            label: Some(label),
+            bind_group_layouts: &self.bind_group_layouts,
+            push_constant_ranges: &[],
+        };
+        let pipeline_layout = self
+            .get_device()
+            .create_pipeline_layout(&pipeline_layout_descriptor);
+
+        let compute_pipeline_descriptor = wgpu::ComputePipelineDescriptor {
+            label: Some(label),
+            layout: Some(&pipeline_layout),
+            module: &shader_module,
+            entry_point: Some(&self.entry_point),
+            compilation_options: Default::default(),
+            cache: self.pipeline_cache,
+        };
+        let compute_pipeline = self
             .get_device()
-            .create_render_pipeline(&render_pipeline_descriptor);
+            .create_compute_pipeline(&compute_pipeline_descriptor);
         self.reset();
 
-        render_pipeline
+        compute_pipeline
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer_backend::test_device::get_test_device;
+
+    const SHADER: &str = r#"
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4f {
+    return vec4f(f32(index), 0.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4f {
+    return vec4f(1.0);
+}
+"#;
+
+    fn write_shader(name: &str, source: &str) -> String {
+        let dir = std::env::temp_dir().join(format!(
+            "learn_wgpu-pipeline-{}-{}",
+            std::process::id(),
+            name
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("shader.wgsl");
+        std::fs::write(&path, source).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn build(shader_filename: &str) -> Result<wgpu::RenderPipeline, PipelineError> {
+        let mut builder = RenderPipelineBuilder::new(&get_test_device().0);
+        builder.set_shader_module(shader_filename, "vs_main", "fs_main");
+        builder.build("Test Pipeline")
+    }
+
+    #[test]
+    fn builds() {
+        build(&write_shader("builds", SHADER)).unwrap();
+    }
+
+    #[test]
+    fn missing_file() {
+        let path = write_shader("missing_file", SHADER).replace("shader.wgsl", "missing.wgsl");
+        let err = build(&path).err().unwrap();
+        assert!(matches!(err, PipelineError::MissingFile(_)), "{err}");
+
+        let source = format!("#include \"missing.wgsl\"\n{}", SHADER);
+        let err = build(&write_shader("missing_include", &source))
+            .err()
+            .unwrap();
+        assert!(matches!(err, PipelineError::MissingFile(_)), "{err}");
+    }
+
+    #[test]
+    fn preprocess_error() {
+        let source = format!("{}#endif\n", SHADER);
+        let err = build(&write_shader("preprocess_error", &source))
+            .err()
+            .unwrap();
+        assert!(matches!(err, PipelineError::Preprocess(_)), "{err}");
+    }
+
+    #[test]
+    fn parse_error() {
+        let source = SHADER.replace("return vec4f(1.0);", "return vec4f(1.0)");
+        let err = build(&write_shader("parse_error", &source)).err().unwrap();
+        assert!(matches!(err, PipelineError::Parse(_)), "{err}");
+    }
+
+    #[test]
+    fn layout_mismatch() {
+        let source = format!(
+            "@group(0) @binding(0) var<uniform> tint: vec4f;\n{}",
+            SHADER.replace("return vec4f(1.0);", "return tint;")
+        );
+        let err = build(&write_shader("layout_mismatch", &source))
+            .err()
+            .unwrap();
+        assert!(
+            matches!(
+                err,
+                PipelineError::LayoutMismatch(ReflectionError::MissingBindGroup { group: 0 })
+            ),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn unsupported_sample_count() {
+        // Float32 formats can't be multisampled without adapter-specific features,
+        // and no format takes 3 samples
+        let path = write_shader("unsupported_sample_count", SHADER);
+        let cases = [
+            (wgpu::TextureFormat::Rgba32Float, 4),
+            (wgpu::TextureFormat::Rgba8Unorm, 3),
+        ];
+        for (pixel_format, sample_count) in cases {
+            let mut builder = RenderPipelineBuilder::new(&get_test_device().0);
+            builder.set_shader_module(&path, "vs_main", "fs_main");
+            builder.set_pixel_format(pixel_format);
+            builder.set_sample_count(sample_count);
+            let err = builder.build("Test Pipeline").err().unwrap();
+            assert!(
+                matches!(
+                    err,
+                    PipelineError::UnsupportedSampleCount { format, count }
+                        if format == pixel_format && count == sample_count
+                ),
+                "{err}"
+            );
+        }
+    }
+
+    #[test]
+    fn missing_polygon_mode_features() {
+        let path = write_shader("missing_polygon_mode_features", SHADER);
+        let cases = [
+            (wgpu::PolygonMode::Line, wgpu::Features::POLYGON_MODE_LINE),
+            (wgpu::PolygonMode::Point, wgpu::Features::POLYGON_MODE_POINT),
+        ];
+        for (polygon_mode, feature) in cases {
+            let mut builder = RenderPipelineBuilder::new(&get_test_device().0);
+            builder.set_shader_module(&path, "vs_main", "fs_main");
+            builder.set_polygon_mode(polygon_mode);
+            let err = builder.build("Test Pipeline").err().unwrap();
+            assert!(
+                matches!(err, PipelineError::MissingFeatures(missing) if missing == feature),
+                "{err}"
+            );
+        }
+    }
+
+    #[test]
+    fn validation_error() {
+        // Reflection doesn't look at the depth format, wgpu does
+        let mut builder = RenderPipelineBuilder::new(&get_test_device().0);
+        builder.set_shader_module(
+            &write_shader("validation_error", SHADER),
+            "vs_main",
+            "fs_main",
+        );
+        builder.set_depth_stencil(
+            wgpu::TextureFormat::Rgba8Unorm,
+            wgpu::CompareFunction::Less,
+            true,
+        );
+        let err = builder.build("Test Pipeline").err().unwrap();
+        assert!(matches!(err, PipelineError::Validation(_)), "{err}");
     }
 }
@@ -0,0 +1,222 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use futures::executor::block_on;
+
+/// Where pipeline caches are kept: `$XDG_CACHE_HOME/learn_wgpu`, falling back to
+/// `~/.cache/learn_wgpu` and then the temp directory.
+pub fn get_cache_dir() -> PathBuf {
+    let base = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .unwrap_or_else(env::temp_dir);
+    base.join("learn_wgpu")
+}
+
+/// One file per adapter.
+pub struct PipelineCache {
+    cache: wgpu::PipelineCache,
+    path: PathBuf,
+}
+
+impl PipelineCache {
+    /// A file from another driver or wgpu version is ignored and replaced on the
+    /// next save. Returns None without `Features::PIPELINE_CACHE`.
+    pub fn load(adapter: &wgpu::Adapter, device: &wgpu::Device, dir: &Path) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::PIPELINE_CACHE) {
+            return None;
+        }
+        let key = wgpu::util::pipeline_cache_key(&adapter.get_info())?;
+        let path = dir.join(key);
+
+        let data = fs::read(&path).ok();
+        let cache = match data.as_deref() {
+            Some(data) => create_cache(device, Some(data)).unwrap_or_else(|err| {
+                log::warn!("Ignoring stale pipeline cache {}: {}", path.display(), err);
+                create_cache(device, None).expect("Can't create an empty pipeline cache")
+            }),
+            None => create_cache(device, None).expect("Can't create an empty pipeline cache"),
+        };
+
+        Some(Self { cache, path })
+    }
+
+    pub fn get_cache(&self) -> &wgpu::PipelineCache {
+        &self.cache
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        match self.cache.get_data() {
+            Some(data) => write_atomically(&self.path, &data),
+            None => Ok(()),
+        }
+    }
+}
+
+/// An interrupted save never leaves a truncated cache behind.
+fn write_atomically(path: &Path, data: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temp_path = path.with_extension("temp");
+    fs::write(&temp_path, data)?;
+    fs::rename(&temp_path, path)
+}
+
+/// Doesn't fall back to an empty cache, so stale data comes back as an error.
+fn create_cache(
+    device: &wgpu::Device,
+    data: Option<&[u8]>,
+) -> Result<wgpu::PipelineCache, wgpu::Error> {
+    let pipeline_cache_descriptor = wgpu::PipelineCacheDescriptor {
+        label: Some("Pipeline Cache"),
+        data,
+        fallback: false,
+    };
+
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    // SAFETY: the data was written by `save` from a cache of the same adapter,
+    // and wgpu validates its header before handing it to the driver.
+    let cache = unsafe { device.create_pipeline_cache(&pipeline_cache_descriptor) };
+    match block_on(device.pop_error_scope()) {
+        Some(err) => Err(err),
+        None => Ok(cache),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer_backend::pipeline::RenderPipelineBuilder;
+    use crate::renderer_backend::test_device::{get_test_adapter, get_test_device};
+
+    const SHADER: &str = r#"
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4f {
+    return vec4f(f32(index), 0.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4f {
+    return vec4f(1.0);
+}
+"#;
+
+    fn get_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!(
+            "learn_wgpu-pipeline_cache-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /// A device with `Features::PIPELINE_CACHE`, if the fallback adapter has it.
+    fn get_cache_device(test: &str) -> Option<wgpu::Device> {
+        let adapter = get_test_adapter();
+        if !adapter.features().contains(wgpu::Features::PIPELINE_CACHE) {
+            eprintln!("Not running '{test}': no pipeline cache on this adapter");
+            return None;
+        }
+        let device_descriptor = wgpu::DeviceDescriptor {
+            required_features: wgpu::Features::PIPELINE_CACHE,
+            required_limits: wgpu::Limits::downlevel_defaults(),
+            label: Some("Pipeline Cache Test Device"),
+            ..Default::default()
+        };
+        let (device, _) = block_on(adapter.request_device(&device_descriptor)).unwrap();
+        Some(device)
+    }
+
+    fn get_cache_path(dir: &Path) -> PathBuf {
+        let key = wgpu::util::pipeline_cache_key(&get_test_adapter().get_info()).unwrap();
+        dir.join(key)
+    }
+
+    fn load(device: &wgpu::Device, dir: &Path) -> PipelineCache {
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let cache = PipelineCache::load(get_test_adapter(), device, dir).unwrap();
+        assert!(block_on(device.pop_error_scope()).is_none());
+        cache
+    }
+
+    fn fill(device: &wgpu::Device, cache: &PipelineCache, dir: &Path) {
+        let path = dir.join("shader.wgsl");
+        fs::create_dir_all(dir).unwrap();
+        fs::write(&path, SHADER).unwrap();
+        let mut builder = RenderPipelineBuilder::new(device);
+        builder.set_shader_module(path.to_str().unwrap(), "vs_main", "fs_main");
+        builder.set_pipeline_cache(cache.get_cache());
+        builder.build("Cached Pipeline").unwrap();
+    }
+
+    #[test]
+    fn needs_the_feature() {
+        let (device, _) = get_test_device();
+        let dir = get_dir("needs_the_feature");
+        assert!(PipelineCache::load(get_test_adapter(), device, &dir).is_none());
+    }
+
+    #[test]
+    fn saved_cache_round_trips() {
+        let Some(device) = get_cache_device("saved_cache_round_trips") else {
+            return;
+        };
+        let dir = get_dir("saved_cache_round_trips");
+        let cache = load(&device, &dir);
+        fill(&device, &cache, &dir);
+        cache.save().unwrap();
+
+        let data = fs::read(get_cache_path(&dir)).unwrap();
+        assert!(create_cache(&device, Some(&data)).is_ok());
+        let reloaded = load(&device, &dir);
+        fill(&device, &reloaded, &dir);
+    }
+
+    #[test]
+    fn stale_or_corrupt_cache_is_ignored() {
+        let Some(device) = get_cache_device("stale_or_corrupt_cache_is_ignored") else {
+            return;
+        };
+        let dir = get_dir("stale_or_corrupt_cache_is_ignored");
+        let cache = load(&device, &dir);
+        fill(&device, &cache, &dir);
+        cache.save().unwrap();
+        let path = get_cache_path(&dir);
+        let data = fs::read(&path).unwrap();
+
+        // Another wgpu version's header, a truncated file and garbage
+        let mut stale = data.clone();
+        stale[8..16].fill(0xff);
+        let truncated = data[..data.len() / 2].to_vec();
+        let garbage = vec![0x5a; 64];
+        for data in [stale, truncated, garbage] {
+            assert!(create_cache(&device, Some(&data)).is_err());
+            fs::write(&path, &data).unwrap();
+            let cache = load(&device, &dir);
+            fill(&device, &cache, &dir);
+        }
+    }
+
+    #[test]
+    fn save_replaces_the_file_whole() {
+        let dir = get_dir("save_replaces_the_file_whole");
+        let path = dir.join("cache");
+        write_atomically(&path, b"first").unwrap();
+        write_atomically(&path, b"second").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        assert_eq!(
+            fs::read_dir(&dir).unwrap().count(),
+            1,
+            "temp file left over"
+        );
+
+        // A write that fails leaves the previous cache as it was
+        fs::create_dir(path.with_extension("temp")).unwrap();
+        assert!(write_atomically(&path, b"third").is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+    }
+}
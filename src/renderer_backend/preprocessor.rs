@@ -0,0 +1,461 @@
+//! A small C-style preprocessor that runs over WGSL before it reaches wgpu.
+//!
+//! Supported directives:
+//! - `#include "file.wgsl"`, resolved relative to the including file. Every file
+//!   is included at most once, however its path is spelled, so shared headers
+//!   need no include guards.
+//! - `#define NAME [value]` and `#undef NAME`. Defined names are replaced by their
+//!   value wherever they appear as a whole identifier.
+//! - `#ifdef NAME`, `#ifndef NAME`, `#else` and `#endif`.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub struct PreprocessError {
+    pub path: PathBuf,
+    pub line: usize,
+    pub message: String,
+    /// The shader file that couldn't be read, if that's what went wrong.
+    pub missing: Option<PathBuf>,
+}
+
+impl fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.path.display(), self.line, self.message)
+    }
+}
+
+impl std::error::Error for PreprocessError {}
+
+pub struct ProcessedShader {
+    pub source: String,
+    /// Every file that went into `source`, starting with the root file.
+    pub files: Vec<PathBuf>,
+    /// The original file and 1-based line of every line in `source`.
+    line_map: Vec<(usize, usize)>,
+}
+
+impl ProcessedShader {
+    /// Maps a 1-based line of the processed source back to the file and line it came from.
+    pub fn map_line(&self, line: usize) -> Option<(&Path, usize)> {
+        let (file, original_line) = *self.line_map.get(line.checked_sub(1)?)?;
+        Some((self.files[file].as_path(), original_line))
+    }
+}
+
+struct Conditional {
+    line: usize,
+    parent_active: bool,
+    active: bool,
+    seen_else: bool,
+}
+
+#[derive(Default, Clone)]
+pub struct Preprocessor {
+    defines: HashMap<String, String>,
+}
+
+impl Preprocessor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn define(&mut self, name: &str, value: &str) {
+        self.defines.insert(name.to_string(), value.to_string());
+    }
+
+    /// The defines passed in from the host, sorted by name.
+    pub fn get_defines(&self) -> Vec<(String, String)> {
+        let mut defines: Vec<(String, String)> = self
+            .defines
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        defines.sort();
+        defines
+    }
+
+    pub fn process(&self, path: &Path) -> Result<ProcessedShader, PreprocessError> {
+        let mut state = State {
+            defines: self.defines.clone(),
+            included: HashSet::new(),
+            output: ProcessedShader {
+                source: String::new(),
+                files: Vec::new(),
+                line_map: Vec::new(),
+            },
+        };
+        state.process_file(path, None)?;
+
+        Ok(state.output)
+    }
+}
+
+struct State {
+    defines: HashMap<String, String>,
+    /// Canonical paths of `output.files`.
+    included: HashSet<PathBuf>,
+    output: ProcessedShader,
+}
+
+impl State {
+    fn process_file(
+        &mut self,
+        path: &Path,
+        included_from: Option<(&Path, usize)>,
+    ) -> Result<(), PreprocessError> {
+        let source_code = fs::read_to_string(path).map_err(|err| match included_from {
+            Some((parent, line)) => PreprocessError {
+                path: parent.to_path_buf(),
+                line,
+                message: format!("can't include {}: {}", path.display(), err),
+                missing: Some(path.to_path_buf()),
+            },
+            None => PreprocessError {
+                path: path.to_path_buf(),
+                line: 0,
+                message: format!("can't read shader source code: {}", err),
+                missing: Some(path.to_path_buf()),
+            },
+        })?;
+
+        let file = self.output.files.len();
+        self.output.files.push(path.to_path_buf());
+        self.included.insert(canonicalize(path));
+
+        let error = |line: usize, message: String| PreprocessError {
+            path: path.to_path_buf(),
+            line,
+            message,
+            missing: None,
+        };
+
+        let mut conditionals: Vec<Conditional> = Vec::new();
+        for (index, text) in source_code.lines().enumerate() {
+            let line = index + 1;
+            let active = conditionals.last().is_none_or(|c| c.active);
+
+            let Some(directive) = text.trim_start().strip_prefix('#') else {
+                if active {
+                    self.push_line(&substitute(text, &self.defines), file, line);
+                }
+                continue;
+            };
+
+            let mut words = directive.split_whitespace();
+            let keyword = words.next().unwrap_or("");
+            let argument = words.next();
+            let rest: Vec<&str> = words.collect();
+
+            match keyword {
+                "ifdef" | "ifndef" => {
+                    let name = argument
+                        .ok_or_else(|| error(line, format!("#{} needs a name", keyword)))?;
+                    let defined = self.defines.contains_key(name);
+                    conditionals.push(Conditional {
+                        line,
+                        parent_active: active,
+                        active: active && (defined == (keyword == "ifdef")),
+                        seen_else: false,
+                    });
+                }
+                "else" => {
+                    let conditional = conditionals
+                        .last_mut()
+                        .ok_or_else(|| error(line, "#else without #ifdef".to_string()))?;
+                    if conditional.seen_else {
+                        return Err(error(line, "duplicate #else".to_string()));
+                    }
+                    conditional.seen_else = true;
+                    conditional.active = conditional.parent_active && !conditional.active;
+                }
+                "endif" => {
+                    conditionals
+                        .pop()
+                        .ok_or_else(|| error(line, "#endif without #ifdef".to_string()))?;
+                }
+                _ if !active => {}
+                "define" => {
+                    let name =
+                        argument.ok_or_else(|| error(line, "#define needs a name".to_string()))?;
+                    self.defines.insert(name.to_string(), rest.join(" "));
+                }
+                "undef" => {
+                    let name =
+                        argument.ok_or_else(|| error(line, "#undef needs a name".to_string()))?;
+                    self.defines.remove(name);
+                }
+                "include" => {
+                    let include = directive.trim_start()["include".len()..].trim();
+                    let filename = include
+                        .strip_prefix('"')
+                        .and_then(|include| include.strip_suffix('"'))
+                        .ok_or_else(|| {
+                            error(line, "expected #include \"file.wgsl\"".to_string())
+                        })?;
+                    let include_path = path.parent().unwrap_or(Path::new("")).join(filename);
+                    if !self.included.contains(&canonicalize(&include_path)) {
+                        self.process_file(&include_path, Some((path, line)))?;
+                    }
+                }
+                _ => return Err(error(line, format!("unknown directive #{}", keyword))),
+            }
+        }
+
+        if let Some(conditional) = conditionals.last() {
+            return Err(error(conditional.line, "unterminated #ifdef".to_string()));
+        }
+
+        Ok(())
+    }
+
+    fn push_line(&mut self, text: &str, file: usize, line: usize) {
+        self.output.source.push_str(text);
+        self.output.source.push('\n');
+        self.output.line_map.push((file, line));
+    }
+}
+
+/// The path with `..`, `.` and links resolved, or as it is if it doesn't exist;
+/// reading it fails then anyway.
+fn canonicalize(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Replaces every whole identifier that names a define with its value.
+fn substitute(text: &str, defines: &HashMap<String, String>) -> String {
+    if defines.is_empty() {
+        return text.to_string();
+    }
+
+    let mut result = String::with_capacity(text.len());
+    let mut identifier = String::new();
+    for c in text.chars().chain(std::iter::once('\n')) {
+        if c.is_alphanumeric() || c == '_' {
+            identifier.push(c);
+            continue;
+        }
+        if !identifier.is_empty() {
+            match defines.get(&identifier) {
+                Some(value) if !value.is_empty() => result.push_str(value),
+                _ => result.push_str(&identifier),
+            }
+            identifier.clear();
+        }
+        if c != '\n' {
+            result.push(c);
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `files` into a fresh directory of their own and returns it.
+    fn write_shaders(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "learn_wgpu-preprocessor-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        for (filename, source) in files {
+            let path = dir.join(filename);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+        dir
+    }
+
+    fn process(name: &str, files: &[(&str, &str)]) -> Result<ProcessedShader, PreprocessError> {
+        let dir = write_shaders(name, files);
+        Preprocessor::new().process(&dir.join(files[0].0))
+    }
+
+    #[test]
+    fn includes_every_file_once() {
+        let shader = process(
+            "include",
+            &[
+                (
+                    "main.wgsl",
+                    "#include \"common.wgsl\"\n#include \"lights/point.wgsl\"\nmain\n",
+                ),
+                ("common.wgsl", "struct Common {}\n"),
+                ("lights/point.wgsl", "#include \"../common.wgsl\"\npoint\n"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(shader.source, "struct Common {}\npoint\nmain\n");
+        assert_eq!(shader.files.len(), 3);
+    }
+
+    #[test]
+    fn includes_a_file_once_however_it_is_spelled() {
+        let shader = process(
+            "include_spelling",
+            &[
+                (
+                    "main.wgsl",
+                    "#include \"common.wgsl\"\n#include \"lights/../common.wgsl\"\n",
+                ),
+                ("common.wgsl", "struct Common {}\n"),
+                ("lights/point.wgsl", ""),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(shader.source, "struct Common {}\n");
+    }
+
+    #[test]
+    fn ifdef_else_endif() {
+        let source = "#ifdef A\na\n#else\nnot a\n#endif\n#ifndef A\nnot a either\n#endif\n";
+        let dir = write_shaders("ifdef", &[("main.wgsl", source)]);
+        let path = dir.join("main.wgsl");
+
+        let mut preprocessor = Preprocessor::new();
+        assert_eq!(
+            preprocessor.process(&path).unwrap().source,
+            "not a\nnot a either\n"
+        );
+        preprocessor.define("A", "");
+        assert_eq!(preprocessor.process(&path).unwrap().source, "a\n");
+    }
+
+    #[test]
+    fn else_stays_inactive_inside_an_inactive_block() {
+        let shader = process(
+            "nested",
+            &[(
+                "main.wgsl",
+                "#ifdef A\n#ifdef B\nb\n#else\nnot b\n#endif\n#endif\nend\n",
+            )],
+        )
+        .unwrap();
+
+        assert_eq!(shader.source, "end\n");
+    }
+
+    #[test]
+    fn defines_replace_whole_identifiers() {
+        let shader = process(
+            "define",
+            &[(
+                "main.wgsl",
+                "#define SIZE 4\narray<f32, SIZE> SIZES\n#undef SIZE\nSIZE\n",
+            )],
+        )
+        .unwrap();
+
+        assert_eq!(shader.source, "array<f32, 4> SIZES\nSIZE\n");
+    }
+
+    #[test]
+    fn directives_in_inactive_blocks_are_ignored() {
+        let shader = process(
+            "inactive_define",
+            &[(
+                "main.wgsl",
+                "#ifdef A\n#define SIZE 4\n#include \"missing.wgsl\"\n#endif\nSIZE\n",
+            )],
+        )
+        .unwrap();
+
+        assert_eq!(shader.source, "SIZE\n");
+    }
+
+    #[test]
+    fn map_line_finds_the_original_file_and_line() {
+        let dir = write_shaders(
+            "map_line",
+            &[
+                ("main.wgsl", "first\n#include \"common.wgsl\"\nthird\n"),
+                ("common.wgsl", "#define X\ncommon\n"),
+            ],
+        );
+        let shader = Preprocessor::new().process(&dir.join("main.wgsl")).unwrap();
+
+        assert_eq!(shader.source, "first\ncommon\nthird\n");
+        assert_eq!(
+            shader.map_line(1),
+            Some((dir.join("main.wgsl").as_path(), 1))
+        );
+        assert_eq!(
+            shader.map_line(2),
+            Some((dir.join("common.wgsl").as_path(), 2))
+        );
+        assert_eq!(
+            shader.map_line(3),
+            Some((dir.join("main.wgsl").as_path(), 3))
+        );
+        assert_eq!(shader.map_line(0), None);
+        assert_eq!(shader.map_line(4), None);
+    }
+
+    #[test]
+    fn missing_file() {
+        let dir = write_shaders("missing_file", &[]);
+        let path = dir.join("main.wgsl");
+        let err = Preprocessor::new().process(&path).err().unwrap();
+
+        assert_eq!(err.path, path);
+        assert_eq!(err.line, 0);
+        assert_eq!(err.missing, Some(path));
+    }
+
+    #[test]
+    fn missing_include() {
+        let dir = write_shaders(
+            "missing_include",
+            &[("main.wgsl", "first\n#include \"missing.wgsl\"\n")],
+        );
+        let err = Preprocessor::new()
+            .process(&dir.join("main.wgsl"))
+            .err()
+            .unwrap();
+
+        assert_eq!(err.path, dir.join("main.wgsl"));
+        assert_eq!(err.line, 2);
+        assert_eq!(err.missing, Some(dir.join("missing.wgsl")));
+    }
+
+    #[test]
+    fn unbalanced_conditionals() {
+        let cases = [
+            ("a\n#endif\n", 2, "#endif without #ifdef"),
+            ("#else\n", 1, "#else without #ifdef"),
+            ("#ifdef A\n#else\n#else\n#endif\n", 3, "duplicate #else"),
+            ("a\n#ifndef A\n", 2, "unterminated #ifdef"),
+            ("#ifdef\n#endif\n", 1, "#ifdef needs a name"),
+        ];
+        for (source, line, message) in cases {
+            let err = process("unbalanced", &[("main.wgsl", source)])
+                .err()
+                .unwrap();
+            assert_eq!(
+                (err.line, err.message.as_str()),
+                (line, message),
+                "{source:?}"
+            );
+            assert_eq!(err.missing, None);
+        }
+    }
+
+    #[test]
+    fn unknown_directive() {
+        let err = process("unknown", &[("main.wgsl", "#pragma once\n")])
+            .err()
+            .unwrap();
+
+        assert_eq!(err.line, 1);
+        assert_eq!(err.message, "unknown directive #pragma");
+    }
+}
@@ -0,0 +1,161 @@
+use std::cell::{Cell, RefCell};
+use std::num::NonZeroU64;
+
+use super::bind_group;
+use super::bind_group_layout::{self, BindGroupLayout};
+
+/// Per-draw parameters, pushed where `Features::PUSH_CONSTANTS` allows and
+/// otherwise in a uniform buffer slot per draw. Shaders declare the block both ways:
+/// ```wgsl
+/// #ifdef PUSH_CONSTANTS
+/// var<push_constant> draw: DrawParams;
+/// #else
+/// @group(1) @binding(0) var<uniform> draw: DrawParams;
+/// #endif
+/// ```
+/// with the same group index that is passed to `new`.
+pub struct PushConstants {
+    stages: wgpu::ShaderStages,
+    size: u32,
+    group: u32,
+    uniform: Option<UniformFallback>,
+}
+
+struct UniformFallback {
+    layout: BindGroupLayout,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    stride: u32,
+    capacity: u32,
+    staging: RefCell<Vec<u8>>,
+    next: Cell<u32>,
+}
+
+impl PushConstants {
+    /// `max_draws` is the most draws between two calls to `flush`.
+    pub fn new(
+        device: &wgpu::Device,
+        stages: wgpu::ShaderStages,
+        size: u32,
+        group: u32,
+        max_draws: u32,
+    ) -> Self {
+        assert!(
+            size > 0 && size.is_multiple_of(wgpu::PUSH_CONSTANT_ALIGNMENT),
+            "Push constant size must be a non-zero multiple of {}, got {}",
+            wgpu::PUSH_CONSTANT_ALIGNMENT,
+            size
+        );
+
+        let native = device.features().contains(wgpu::Features::PUSH_CONSTANTS)
+            && size <= device.limits().max_push_constant_size;
+        let uniform = (!native).then(|| UniformFallback::new(device, stages, size, max_draws));
+
+        Self {
+            stages,
+            size,
+            group,
+            uniform,
+        }
+    }
+
+    pub fn is_native(&self) -> bool {
+        self.uniform.is_none()
+    }
+
+    pub fn get_range(&self) -> wgpu::PushConstantRange {
+        wgpu::PushConstantRange {
+            stages: self.stages,
+            range: 0..self.size,
+        }
+    }
+
+    pub fn get_group(&self) -> u32 {
+        self.group
+    }
+
+    pub fn get_bind_group_layout(&self) -> Option<&BindGroupLayout> {
+        self.uniform.as_ref().map(|uniform| &uniform.layout)
+    }
+
+    pub fn set(&self, renderpass: &mut wgpu::RenderPass<'_>, data: &[u8]) {
+        assert_eq!(
+            data.len(),
+            self.size as usize,
+            "Push constant data must be {} bytes",
+            self.size
+        );
+
+        let Some(uniform) = &self.uniform else {
+            renderpass.set_push_constants(self.stages, 0, data);
+            return;
+        };
+
+        let slot = uniform.next.get();
+        assert!(
+            slot < uniform.capacity,
+            "More than {} draws set push constants before a flush",
+            uniform.capacity
+        );
+        uniform.next.set(slot + 1);
+
+        let offset = slot * uniform.stride;
+        uniform.staging.borrow_mut()[offset as usize..][..data.len()].copy_from_slice(data);
+        renderpass.set_bind_group(self.group, &uniform.bind_group, &[offset]);
+    }
+
+    /// Call it before submitting the passes that used the parameters.
+    pub fn flush(&self, queue: &wgpu::Queue) {
+        let Some(uniform) = &self.uniform else {
+            return;
+        };
+
+        let used = (uniform.next.replace(0) * uniform.stride) as usize;
+        if used > 0 {
+            queue.write_buffer(&uniform.buffer, 0, &uniform.staging.borrow()[..used]);
+        }
+    }
+}
+
+impl UniformFallback {
+    fn new(device: &wgpu::Device, stages: wgpu::ShaderStages, size: u32, max_draws: u32) -> Self {
+        let alignment = device.limits().min_uniform_buffer_offset_alignment;
+        let stride = size.div_ceil(alignment) * alignment;
+        let capacity = max_draws.max(1);
+
+        let layout: BindGroupLayout;
+        {
+            let mut builder = bind_group_layout::Builder::new(device);
+            builder.add_uniform_buffer(0, stages, true, NonZeroU64::new(size as u64));
+            layout = builder.build("Push Constant Fallback Layout");
+        }
+
+        let buffer_descriptor = wgpu::BufferDescriptor {
+            label: Some("Push Constant Fallback Buffer"),
+            size: (stride * capacity) as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        };
+        let buffer = device.create_buffer(&buffer_descriptor);
+
+        let bind_group: wgpu::BindGroup;
+        {
+            let mut builder = bind_group::Builder::new(device);
+            builder.set_layout(&layout);
+            builder.add_buffer_range(0, &buffer, 0, NonZeroU64::new(size as u64));
+            bind_group = builder
+                .build("Push Constant Fallback Bind Group")
+                .unwrap_or_else(|err| panic!("Can't build the push constant fallback! {}", err));
+        }
+
+        Self {
+            layout,
+            buffer,
+            bind_group,
+            stride,
+            capacity,
+            staging: RefCell::new(vec![0; (stride * capacity) as usize]),
+            next: Cell::new(0),
+        }
+    }
+}
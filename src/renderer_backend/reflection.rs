@@ -0,0 +1,1013 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::num::NonZeroU64;
+
+use wgpu::naga;
+
+use super::preprocessor::{PreprocessError, ProcessedShader};
+
+#[derive(Debug)]
+pub enum ReflectionError {
+    /// The WGSL failed to parse or validate; the message points at the original file.
+    Shader(String),
+    MissingEntryPoint {
+        name: String,
+        stage: naga::ShaderStage,
+    },
+    UnsupportedBinding {
+        group: u32,
+        binding: u32,
+        reason: String,
+    },
+    UnsupportedVertexInput {
+        location: u32,
+    },
+    MissingVertexAttribute {
+        location: u32,
+        format: wgpu::VertexFormat,
+    },
+    VertexFormatMismatch {
+        location: u32,
+        shader: wgpu::VertexFormat,
+        layout: wgpu::VertexFormat,
+    },
+    MissingColorTarget {
+        location: u32,
+    },
+    ColorTargetMismatch {
+        location: u32,
+        kind: naga::ScalarKind,
+        format: wgpu::TextureFormat,
+    },
+    MissingConstant {
+        name: String,
+    },
+    MissingBindGroup {
+        group: u32,
+    },
+    MissingBinding {
+        group: u32,
+        binding: u32,
+    },
+    BindingTypeMismatch {
+        group: u32,
+        binding: u32,
+        shader: wgpu::BindingType,
+        layout: wgpu::BindingType,
+    },
+    VisibilityMismatch {
+        group: u32,
+        binding: u32,
+        shader: wgpu::ShaderStages,
+        layout: wgpu::ShaderStages,
+    },
+}
+
+impl fmt::Display for ReflectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReflectionError::Shader(message) => write!(f, "{}", message),
+            ReflectionError::MissingEntryPoint { name, stage } => {
+                write!(f, "shader has no {:?} entry point named '{}'", stage, name)
+            }
+            ReflectionError::UnsupportedBinding {
+                group,
+                binding,
+                reason,
+            } => write!(f, "@group({}) @binding({}): {}", group, binding, reason),
+            ReflectionError::UnsupportedVertexInput { location } => write!(
+                f,
+                "vertex input @location({}) has a type no vertex format can feed",
+                location
+            ),
+            ReflectionError::MissingVertexAttribute { location, format } => write!(
+                f,
+                "shader reads @location({}) as {:?}, but no vertex buffer layout provides it",
+                location, format
+            ),
+            ReflectionError::VertexFormatMismatch {
+                location,
+                shader,
+                layout,
+            } => write!(
+                f,
+                "shader reads @location({}) as {:?}, but the vertex buffer layout provides {:?}",
+                location, shader, layout
+            ),
+            ReflectionError::MissingColorTarget { location } => write!(
+                f,
+                "shader writes @location({}), but the pipeline has no color target for it",
+                location
+            ),
+            ReflectionError::ColorTargetMismatch {
+                location,
+                kind,
+                format,
+            } => write!(
+                f,
+                "shader writes {:?} values to @location({}), but its color target is {:?}",
+                kind, location, format
+            ),
+            ReflectionError::MissingConstant { name } => {
+                write!(f, "shader has no override constant named '{}'", name)
+            }
+            ReflectionError::MissingBindGroup { group } => write!(
+                f,
+                "shader uses @group({}), but no bind group layout was added for it",
+                group
+            ),
+            ReflectionError::MissingBinding { group, binding } => write!(
+                f,
+                "shader uses @group({}) @binding({}), but its bind group layout has no such entry",
+                group, binding
+            ),
+            ReflectionError::BindingTypeMismatch {
+                group,
+                binding,
+                shader,
+                layout,
+            } => write!(
+                f,
+                "@group({}) @binding({}) is {:?} in the shader, but {:?} in the bind group layout",
+                group, binding, shader, layout
+            ),
+            ReflectionError::VisibilityMismatch {
+                group,
+                binding,
+                shader,
+                layout,
+            } => write!(
+                f,
+                "@group({}) @binding({}) is used by {:?}, but only visible to {:?}",
+                group, binding, shader, layout
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReflectionError {}
+
+impl From<PreprocessError> for ReflectionError {
+    fn from(err: PreprocessError) -> Self {
+        ReflectionError::Shader(err.to_string())
+    }
+}
+
+pub struct ShaderReflection {
+    pub bind_groups: BTreeMap<u32, Vec<wgpu::BindGroupLayoutEntry>>,
+    pub vertex_inputs: Vec<(u32, wgpu::VertexFormat)>,
+    pub fragment_outputs: Vec<(u32, naga::ScalarKind)>,
+    /// Names of the `override` constants, and the `@id` of those that have one.
+    pub constants: Vec<String>,
+}
+
+impl ShaderReflection {
+    pub fn check_vertex_buffer_layouts(
+        &self,
+        layouts: &[wgpu::VertexBufferLayout<'_>],
+    ) -> Result<(), ReflectionError> {
+        for &(location, format) in &self.vertex_inputs {
+            let attribute = layouts
+                .iter()
+                .flat_map(|layout| layout.attributes.iter())
+                .find(|attribute| attribute.shader_location == location)
+                .ok_or(ReflectionError::MissingVertexAttribute { location, format })?;
+
+            if get_format_kind(attribute.format) != get_format_kind(format) {
+                return Err(ReflectionError::VertexFormatMismatch {
+                    location,
+                    shader: format,
+                    layout: attribute.format,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn check_color_targets(
+        &self,
+        formats: &[wgpu::TextureFormat],
+    ) -> Result<(), ReflectionError> {
+        for &(location, kind) in &self.fragment_outputs {
+            let format = *formats
+                .get(location as usize)
+                .ok_or(ReflectionError::MissingColorTarget { location })?;
+
+            let target_kind = match format.sample_type(None, None) {
+                Some(wgpu::TextureSampleType::Uint) => naga::ScalarKind::Uint,
+                Some(wgpu::TextureSampleType::Sint) => naga::ScalarKind::Sint,
+                _ => naga::ScalarKind::Float,
+            };
+            if target_kind != kind {
+                return Err(ReflectionError::ColorTargetMismatch {
+                    location,
+                    kind,
+                    format,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn check_bind_group_layout(
+        &self,
+        group: u32,
+        entries: &[wgpu::BindGroupLayoutEntry],
+    ) -> Result<(), ReflectionError> {
+        let Some(shader_entries) = self.bind_groups.get(&group) else {
+            return Ok(());
+        };
+
+        for shader_entry in shader_entries {
+            let binding = shader_entry.binding;
+            let entry = entries
+                .iter()
+                .find(|entry| entry.binding == binding)
+                .ok_or(ReflectionError::MissingBinding { group, binding })?;
+
+            if !is_compatible(&shader_entry.ty, &entry.ty) || shader_entry.count != entry.count {
+                return Err(ReflectionError::BindingTypeMismatch {
+                    group,
+                    binding,
+                    shader: shader_entry.ty,
+                    layout: entry.ty,
+                });
+            }
+            if !entry.visibility.contains(shader_entry.visibility) {
+                return Err(ReflectionError::VisibilityMismatch {
+                    group,
+                    binding,
+                    shader: shader_entry.visibility,
+                    layout: entry.visibility,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Points at the original file rather than the preprocessed source.
+fn describe(
+    shader: &ProcessedShader,
+    location: Option<naga::SourceLocation>,
+    message: &str,
+) -> String {
+    let mapped = location.and_then(|location| {
+        let (path, line) = shader.map_line(location.line_number as usize)?;
+        Some(format!(
+            "{}:{}:{}",
+            path.display(),
+            line,
+            location.line_position
+        ))
+    });
+    let origin = mapped.unwrap_or_else(|| shader.files[0].display().to_string());
+
+    format!("{}: {}", origin, message)
+}
+
+pub fn parse_wgsl(
+    shader: &ProcessedShader,
+) -> Result<(naga::Module, naga::valid::ModuleInfo), String> {
+    let source_code = &shader.source;
+    let module = naga::front::wgsl::parse_str(source_code)
+        .map_err(|err| describe(shader, err.location(source_code), err.message()))?;
+
+    let mut validator = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    );
+    let info = validator.validate(&module).map_err(|err| {
+        let mut message = err.as_inner().to_string();
+        let mut source = std::error::Error::source(err.as_inner());
+        while let Some(cause) = source {
+            message.push_str(&format!(": {}", cause));
+            source = cause.source();
+        }
+        describe(shader, err.location(source_code), &message)
+    })?;
+
+    Ok((module, info))
+}
+
+pub fn reflect(
+    shader: &ProcessedShader,
+    entry_points: &[(&str, naga::ShaderStage)],
+) -> Result<ShaderReflection, ReflectionError> {
+    let (module, info) = parse_wgsl(shader).map_err(ReflectionError::Shader)?;
+
+    let mut bind_groups: BTreeMap<u32, Vec<wgpu::BindGroupLayoutEntry>> = BTreeMap::new();
+    let mut vertex_inputs = Vec::new();
+    let mut fragment_outputs = Vec::new();
+
+    for &(name, stage) in entry_points {
+        let (index, entry_point) = module
+            .entry_points
+            .iter()
+            .enumerate()
+            .find(|(_, entry_point)| entry_point.name == name && entry_point.stage == stage)
+            .ok_or_else(|| ReflectionError::MissingEntryPoint {
+                name: name.to_string(),
+                stage,
+            })?;
+        let function_info = info.get_entry_point(index);
+
+        for (handle, variable) in module.global_variables.iter() {
+            let Some(resource_binding) = &variable.binding else {
+                continue;
+            };
+            if function_info[handle].is_empty() {
+                continue;
+            }
+
+            let visibility = get_shader_stage(stage);
+            let entries = bind_groups.entry(resource_binding.group).or_default();
+            if let Some(entry) = entries
+                .iter_mut()
+                .find(|entry| entry.binding == resource_binding.binding)
+            {
+                entry.visibility |= visibility;
+                continue;
+            }
+
+            let (ty, count) = get_binding_type(&module, variable).map_err(|reason| {
+                ReflectionError::UnsupportedBinding {
+                    group: resource_binding.group,
+                    binding: resource_binding.binding,
+                    reason,
+                }
+            })?;
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: resource_binding.binding,
+                visibility,
+                ty,
+                count,
+            });
+        }
+
+        if stage == naga::ShaderStage::Vertex {
+            let mut inputs = Vec::new();
+            for argument in &entry_point.function.arguments {
+                collect_locations(&module, argument.ty, argument.binding.as_ref(), &mut inputs);
+            }
+            for (location, inner) in inputs {
+                let format = get_vertex_format(inner)
+                    .ok_or(ReflectionError::UnsupportedVertexInput { location })?;
+                vertex_inputs.push((location, format));
+            }
+        }
+
+        if stage == naga::ShaderStage::Fragment {
+            let mut outputs = Vec::new();
+            if let Some(result) = &entry_point.function.result {
+                collect_locations(&module, result.ty, result.binding.as_ref(), &mut outputs);
+            }
+            for (location, inner) in outputs {
+                if let Some(kind) = inner.scalar_kind() {
+                    fragment_outputs.push((location, kind));
+                }
+            }
+        }
+    }
+
+    for entries in bind_groups.values_mut() {
+        entries.sort_by_key(|entry| entry.binding);
+    }
+    vertex_inputs.sort_by_key(|&(location, _)| location);
+    fragment_outputs.sort_by_key(|&(location, _)| location);
+
+    let mut constants = Vec::new();
+    for (_, constant) in module.overrides.iter() {
+        constants.extend(constant.name.clone());
+        constants.extend(constant.id.map(|id| id.to_string()));
+    }
+
+    Ok(ShaderReflection {
+        bind_groups,
+        vertex_inputs,
+        fragment_outputs,
+        constants,
+    })
+}
+
+fn get_shader_stage(stage: naga::ShaderStage) -> wgpu::ShaderStages {
+    match stage {
+        naga::ShaderStage::Vertex => wgpu::ShaderStages::VERTEX,
+        naga::ShaderStage::Fragment => wgpu::ShaderStages::FRAGMENT,
+        naga::ShaderStage::Compute => wgpu::ShaderStages::COMPUTE,
+        _ => wgpu::ShaderStages::NONE,
+    }
+}
+
+fn collect_locations<'a>(
+    module: &'a naga::Module,
+    ty: naga::Handle<naga::Type>,
+    binding: Option<&naga::Binding>,
+    locations: &mut Vec<(u32, &'a naga::TypeInner)>,
+) {
+    match (binding, &module.types[ty].inner) {
+        (Some(naga::Binding::Location { location, .. }), inner) => {
+            locations.push((*location, inner));
+        }
+        (None, naga::TypeInner::Struct { members, .. }) => {
+            for member in members {
+                collect_locations(module, member.ty, member.binding.as_ref(), locations);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn get_vertex_format(inner: &naga::TypeInner) -> Option<wgpu::VertexFormat> {
+    use naga::{ScalarKind as Kind, VectorSize as Size};
+    use wgpu::VertexFormat as Format;
+
+    let (size, scalar) = match inner {
+        naga::TypeInner::Scalar(scalar) => (None, *scalar),
+        naga::TypeInner::Vector { size, scalar } => (Some(*size), *scalar),
+        _ => return None,
+    };
+    if scalar.width != 4 {
+        return None;
+    }
+
+    let format = match (scalar.kind, size) {
+        (Kind::Float, None) => Format::Float32,
+        (Kind::Float, Some(Size::Bi)) => Format::Float32x2,
+        (Kind::Float, Some(Size::Tri)) => Format::Float32x3,
+        (Kind::Float, Some(Size::Quad)) => Format::Float32x4,
+        (Kind::Sint, None) => Format::Sint32,
+        (Kind::Sint, Some(Size::Bi)) => Format::Sint32x2,
+        (Kind::Sint, Some(Size::Tri)) => Format::Sint32x3,
+        (Kind::Sint, Some(Size::Quad)) => Format::Sint32x4,
+        (Kind::Uint, None) => Format::Uint32,
+        (Kind::Uint, Some(Size::Bi)) => Format::Uint32x2,
+        (Kind::Uint, Some(Size::Tri)) => Format::Uint32x3,
+        (Kind::Uint, Some(Size::Quad)) => Format::Uint32x4,
+        _ => return None,
+    };
+
+    Some(format)
+}
+
+/// Normalized and half-float formats are read as `f32`.
+fn get_format_kind(format: wgpu::VertexFormat) -> naga::ScalarKind {
+    use wgpu::VertexFormat as Format;
+
+    match format {
+        Format::Uint8
+        | Format::Uint8x2
+        | Format::Uint8x4
+        | Format::Uint16
+        | Format::Uint16x2
+        | Format::Uint16x4
+        | Format::Uint32
+        | Format::Uint32x2
+        | Format::Uint32x3
+        | Format::Uint32x4 => naga::ScalarKind::Uint,
+        Format::Sint8
+        | Format::Sint8x2
+        | Format::Sint8x4
+        | Format::Sint16
+        | Format::Sint16x2
+        | Format::Sint16x4
+        | Format::Sint32
+        | Format::Sint32x2
+        | Format::Sint32x3
+        | Format::Sint32x4 => naga::ScalarKind::Sint,
+        _ => naga::ScalarKind::Float,
+    }
+}
+
+fn get_binding_type(
+    module: &naga::Module,
+    variable: &naga::GlobalVariable,
+) -> Result<(wgpu::BindingType, Option<std::num::NonZeroU32>), String> {
+    let (ty, count) = match module.types[variable.ty].inner {
+        naga::TypeInner::BindingArray { base, size } => {
+            let count = match size {
+                naga::ArraySize::Constant(count) => Some(count),
+                _ => return Err("binding arrays need a constant size".to_string()),
+            };
+            (base, count)
+        }
+        _ => (variable.ty, None),
+    };
+
+    let binding_type = match variable.space {
+        naga::AddressSpace::Uniform => wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: NonZeroU64::new(module.types[ty].inner.size(module.to_ctx()) as u64),
+        },
+        naga::AddressSpace::Storage { access } => wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage {
+                read_only: !access.contains(naga::StorageAccess::STORE),
+            },
+            has_dynamic_offset: false,
+            min_binding_size: NonZeroU64::new(module.types[ty].inner.size(module.to_ctx()) as u64),
+        },
+        naga::AddressSpace::Handle => match module.types[ty].inner {
+            naga::TypeInner::Sampler { comparison: true } => {
+                wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison)
+            }
+            naga::TypeInner::Sampler { comparison: false } => {
+                wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering)
+            }
+            naga::TypeInner::Image {
+                dim,
+                arrayed,
+                class,
+            } => {
+                let view_dimension = get_view_dimension(dim, arrayed);
+                match class {
+                    naga::ImageClass::Sampled { kind, multi } => wgpu::BindingType::Texture {
+                        sample_type: match kind {
+                            naga::ScalarKind::Sint => wgpu::TextureSampleType::Sint,
+                            naga::ScalarKind::Uint => wgpu::TextureSampleType::Uint,
+                            _ => wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        view_dimension,
+                        multisampled: multi,
+                    },
+                    naga::ImageClass::Depth { multi } => wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension,
+                        multisampled: multi,
+                    },
+                    naga::ImageClass::Storage { format, access } => {
+                        wgpu::BindingType::StorageTexture {
+                            access: get_storage_access(access),
+                            format: get_storage_format(format)?,
+                            view_dimension,
+                        }
+                    }
+                }
+            }
+            _ => return Err("unsupported handle type".to_string()),
+        },
+        space => return Err(format!("unsupported address space {:?}", space)),
+    };
+
+    Ok((binding_type, count))
+}
+
+fn get_view_dimension(dim: naga::ImageDimension, arrayed: bool) -> wgpu::TextureViewDimension {
+    match (dim, arrayed) {
+        (naga::ImageDimension::D1, _) => wgpu::TextureViewDimension::D1,
+        (naga::ImageDimension::D2, false) => wgpu::TextureViewDimension::D2,
+        (naga::ImageDimension::D2, true) => wgpu::TextureViewDimension::D2Array,
+        (naga::ImageDimension::D3, _) => wgpu::TextureViewDimension::D3,
+        (naga::ImageDimension::Cube, false) => wgpu::TextureViewDimension::Cube,
+        (naga::ImageDimension::Cube, true) => wgpu::TextureViewDimension::CubeArray,
+    }
+}
+
+fn get_storage_access(access: naga::StorageAccess) -> wgpu::StorageTextureAccess {
+    let load = access.contains(naga::StorageAccess::LOAD);
+    let store = access.contains(naga::StorageAccess::STORE);
+    match (load, store) {
+        (true, true) => wgpu::StorageTextureAccess::ReadWrite,
+        (true, false) => wgpu::StorageTextureAccess::ReadOnly,
+        _ => wgpu::StorageTextureAccess::WriteOnly,
+    }
+}
+
+fn get_storage_format(format: naga::StorageFormat) -> Result<wgpu::TextureFormat, String> {
+    use naga::StorageFormat as Naga;
+    use wgpu::TextureFormat as Wgpu;
+
+    let format = match format {
+        Naga::R32Uint => Wgpu::R32Uint,
+        Naga::R32Sint => Wgpu::R32Sint,
+        Naga::R32Float => Wgpu::R32Float,
+        Naga::Rg32Uint => Wgpu::Rg32Uint,
+        Naga::Rg32Sint => Wgpu::Rg32Sint,
+        Naga::Rg32Float => Wgpu::Rg32Float,
+        Naga::Rgba8Unorm => Wgpu::Rgba8Unorm,
+        Naga::Rgba8Snorm => Wgpu::Rgba8Snorm,
+        Naga::Rgba8Uint => Wgpu::Rgba8Uint,
+        Naga::Rgba8Sint => Wgpu::Rgba8Sint,
+        Naga::Bgra8Unorm => Wgpu::Bgra8Unorm,
+        Naga::Rgba16Uint => Wgpu::Rgba16Uint,
+        Naga::Rgba16Sint => Wgpu::Rgba16Sint,
+        Naga::Rgba16Float => Wgpu::Rgba16Float,
+        Naga::Rgba32Uint => Wgpu::Rgba32Uint,
+        Naga::Rgba32Sint => Wgpu::Rgba32Sint,
+        Naga::Rgba32Float => Wgpu::Rgba32Float,
+        format => return Err(format!("unsupported storage texture format {:?}", format)),
+    };
+
+    Ok(format)
+}
+
+/// Reflection can't tell filterable from non-filterable, sizes are only a lower
+/// bound, and dynamic offsets are invisible to the shader.
+fn is_compatible(shader: &wgpu::BindingType, layout: &wgpu::BindingType) -> bool {
+    use wgpu::BindingType as Type;
+
+    match (shader, layout) {
+        (
+            Type::Buffer {
+                ty: shader_ty,
+                min_binding_size: shader_size,
+                ..
+            },
+            Type::Buffer {
+                ty: layout_ty,
+                min_binding_size: layout_size,
+                ..
+            },
+        ) => {
+            shader_ty == layout_ty
+                && match (shader_size, layout_size) {
+                    (Some(shader_size), Some(layout_size)) => layout_size >= shader_size,
+                    _ => true,
+                }
+        }
+        (
+            Type::Texture {
+                sample_type: shader_sample_type,
+                view_dimension: shader_view_dimension,
+                multisampled: shader_multisampled,
+            },
+            Type::Texture {
+                sample_type: layout_sample_type,
+                view_dimension: layout_view_dimension,
+                multisampled: layout_multisampled,
+            },
+        ) => {
+            let sample_types_match = matches!(
+                (shader_sample_type, layout_sample_type),
+                (
+                    wgpu::TextureSampleType::Float { .. },
+                    wgpu::TextureSampleType::Float { .. }
+                )
+            ) || shader_sample_type == layout_sample_type;
+            sample_types_match
+                && shader_view_dimension == layout_view_dimension
+                && shader_multisampled == layout_multisampled
+        }
+        (Type::Sampler(shader_sampler), Type::Sampler(layout_sampler)) => {
+            (*shader_sampler == wgpu::SamplerBindingType::Comparison)
+                == (*layout_sampler == wgpu::SamplerBindingType::Comparison)
+        }
+        _ => shader == layout,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer_backend::preprocessor::Preprocessor;
+
+    const SHADER: &str = r#"
+struct Params {
+    tint: vec4<f32>,
+}
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var myTexture: texture_2d<f32>;
+@group(0) @binding(2) var mySampler: sampler;
+
+override scale: f32 = 1.0;
+
+struct VertexPayload {
+    @builtin(position) position: vec4<f32>,
+    @location(0) texCoord: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@location(0) position: vec3<f32>, @location(1) texCoord: vec2<f32>) -> VertexPayload {
+    var out = VertexPayload();
+    out.position = vec4<f32>(position * scale * params.tint.w, 1.0);
+    out.texCoord = texCoord;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexPayload) -> @location(0) vec4<f32> {
+    return textureSample(myTexture, mySampler, in.texCoord) * params.tint;
+}
+"#;
+
+    const VERTEX_AND_FRAGMENT: wgpu::ShaderStages = wgpu::ShaderStages::VERTEX_FRAGMENT;
+
+    fn reflect_source(
+        name: &str,
+        source: &str,
+        entry_points: &[(&str, naga::ShaderStage)],
+    ) -> Result<ShaderReflection, ReflectionError> {
+        let dir = std::env::temp_dir().join(format!(
+            "learn_wgpu-reflection-{}-{}",
+            std::process::id(),
+            name
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("shader.wgsl");
+        std::fs::write(&path, source).unwrap();
+
+        let shader = Preprocessor::new().process(&path)?;
+        reflect(&shader, entry_points)
+    }
+
+    fn reflect_shader(name: &str) -> ShaderReflection {
+        let entry_points = [
+            ("vs_main", naga::ShaderStage::Vertex),
+            ("fs_main", naga::ShaderStage::Fragment),
+        ];
+        reflect_source(name, SHADER, &entry_points).unwrap()
+    }
+
+    fn entry(
+        binding: u32,
+        visibility: wgpu::ShaderStages,
+        ty: wgpu::BindingType,
+    ) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty,
+            count: None,
+        }
+    }
+
+    fn uniform(min_binding_size: u64) -> wgpu::BindingType {
+        wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: NonZeroU64::new(min_binding_size),
+        }
+    }
+
+    fn texture(sample_type: wgpu::TextureSampleType) -> wgpu::BindingType {
+        wgpu::BindingType::Texture {
+            sample_type,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        }
+    }
+
+    /// Entries matching `SHADER`.
+    fn get_entries() -> Vec<wgpu::BindGroupLayoutEntry> {
+        vec![
+            entry(0, VERTEX_AND_FRAGMENT, uniform(16)),
+            entry(
+                1,
+                wgpu::ShaderStages::FRAGMENT,
+                texture(wgpu::TextureSampleType::Float { filterable: false }),
+            ),
+            entry(
+                2,
+                wgpu::ShaderStages::FRAGMENT,
+                wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            ),
+        ]
+    }
+
+    fn vertex_layout(attributes: &[wgpu::VertexAttribute]) -> wgpu::VertexBufferLayout<'_> {
+        wgpu::VertexBufferLayout {
+            array_stride: 20,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes,
+        }
+    }
+
+    #[test]
+    fn reflects_bindings_inputs_outputs_and_constants() {
+        let reflection = reflect_shader("reflect");
+
+        assert_eq!(reflection.bind_groups.len(), 1);
+        let entries = &reflection.bind_groups[&0];
+        assert_eq!(entries[0], entry(0, VERTEX_AND_FRAGMENT, uniform(16)));
+        assert_eq!(
+            entries[1],
+            entry(
+                1,
+                wgpu::ShaderStages::FRAGMENT,
+                texture(wgpu::TextureSampleType::Float { filterable: true })
+            )
+        );
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            reflection.vertex_inputs,
+            [
+                (0, wgpu::VertexFormat::Float32x3),
+                (1, wgpu::VertexFormat::Float32x2)
+            ]
+        );
+        assert_eq!(reflection.fragment_outputs, [(0, naga::ScalarKind::Float)]);
+        assert_eq!(reflection.constants, ["scale"]);
+
+        reflection
+            .check_bind_group_layout(0, &get_entries())
+            .unwrap();
+        reflection
+            .check_color_targets(&[wgpu::TextureFormat::Rgba8Unorm])
+            .unwrap();
+    }
+
+    #[test]
+    fn missing_entry_point() {
+        let err = reflect_source(
+            "missing_entry_point",
+            SHADER,
+            &[("fs_main", naga::ShaderStage::Vertex)],
+        )
+        .err()
+        .unwrap();
+
+        assert!(
+            matches!(
+                &err,
+                ReflectionError::MissingEntryPoint { name, stage: naga::ShaderStage::Vertex }
+                    if name == "fs_main"
+            ),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn invalid_shader_points_at_the_file() {
+        let err = reflect_source("invalid", "fn main( {}\n", &[])
+            .err()
+            .unwrap();
+
+        assert!(
+            matches!(&err, ReflectionError::Shader(message) if message.contains("shader.wgsl:1:")),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn missing_binding() {
+        let reflection = reflect_shader("missing_binding");
+        let mut entries = get_entries();
+        entries.remove(2);
+        let err = reflection
+            .check_bind_group_layout(0, &entries)
+            .err()
+            .unwrap();
+
+        assert!(
+            matches!(
+                err,
+                ReflectionError::MissingBinding {
+                    group: 0,
+                    binding: 2
+                }
+            ),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn binding_type_mismatch() {
+        let reflection = reflect_shader("binding_type");
+        let mismatches = [
+            // A storage buffer where the shader has a uniform
+            entry(
+                0,
+                VERTEX_AND_FRAGMENT,
+                wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+            ),
+            // A uniform smaller than the shader's struct
+            entry(0, VERTEX_AND_FRAGMENT, uniform(8)),
+            // An integer texture where the shader samples floats
+            entry(
+                1,
+                wgpu::ShaderStages::FRAGMENT,
+                texture(wgpu::TextureSampleType::Uint),
+            ),
+            // A comparison sampler where the shader has a plain one
+            entry(
+                2,
+                wgpu::ShaderStages::FRAGMENT,
+                wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+            ),
+        ];
+
+        for mismatch in mismatches {
+            let mut entries = get_entries();
+            entries[mismatch.binding as usize] = mismatch;
+            let err = reflection
+                .check_bind_group_layout(0, &entries)
+                .err()
+                .unwrap();
+            assert!(
+                matches!(
+                    err,
+                    ReflectionError::BindingTypeMismatch { group: 0, binding, layout, .. }
+                        if binding == mismatch.binding && layout == mismatch.ty
+                ),
+                "{err}"
+            );
+        }
+    }
+
+    #[test]
+    fn visibility_mismatch() {
+        let reflection = reflect_shader("visibility");
+        let mut entries = get_entries();
+        entries[0].visibility = wgpu::ShaderStages::FRAGMENT;
+        let err = reflection
+            .check_bind_group_layout(0, &entries)
+            .err()
+            .unwrap();
+
+        assert!(
+            matches!(
+                err,
+                ReflectionError::VisibilityMismatch {
+                    group: 0,
+                    binding: 0,
+                    shader: VERTEX_AND_FRAGMENT,
+                    layout: wgpu::ShaderStages::FRAGMENT,
+                }
+            ),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn vertex_format_mismatch() {
+        let reflection = reflect_shader("vertex_format");
+        let attributes = wgpu::vertex_attr_array![0 => Float32x3, 1 => Uint32x2];
+        let err = reflection
+            .check_vertex_buffer_layouts(&[vertex_layout(&attributes)])
+            .err()
+            .unwrap();
+
+        assert!(
+            matches!(
+                err,
+                ReflectionError::VertexFormatMismatch {
+                    location: 1,
+                    shader: wgpu::VertexFormat::Float32x2,
+                    layout: wgpu::VertexFormat::Uint32x2,
+                }
+            ),
+            "{err}"
+        );
+
+        // Normalized formats are read as floats
+        let attributes = wgpu::vertex_attr_array![0 => Float32x3, 1 => Unorm16x2];
+        reflection
+            .check_vertex_buffer_layouts(&[vertex_layout(&attributes)])
+            .unwrap();
+    }
+
+    #[test]
+    fn missing_vertex_attribute() {
+        let reflection = reflect_shader("vertex_attribute");
+        let attributes = wgpu::vertex_attr_array![0 => Float32x3];
+        let err = reflection
+            .check_vertex_buffer_layouts(&[vertex_layout(&attributes)])
+            .err()
+            .unwrap();
+
+        assert!(
+            matches!(
+                err,
+                ReflectionError::MissingVertexAttribute {
+                    location: 1,
+                    format: wgpu::VertexFormat::Float32x2,
+                }
+            ),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn color_target_mismatch() {
+        let reflection = reflect_shader("color_target");
+
+        let err = reflection.check_color_targets(&[]).err().unwrap();
+        assert!(
+            matches!(err, ReflectionError::MissingColorTarget { location: 0 }),
+            "{err}"
+        );
+
+        let err = reflection
+            .check_color_targets(&[wgpu::TextureFormat::R32Uint])
+            .err()
+            .unwrap();
+        assert!(
+            matches!(
+                err,
+                ReflectionError::ColorTargetMismatch {
+                    location: 0,
+                    kind: naga::ScalarKind::Float,
+                    format: wgpu::TextureFormat::R32Uint,
+                }
+            ),
+            "{err}"
+        );
+    }
+}
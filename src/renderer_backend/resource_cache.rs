@@ -0,0 +1,238 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::bind_group_layout::BindGroupLayout;
+use super::image_loader;
+use super::material::{LoadOptions, MaterialTexture, PreparedImage};
+use super::mipmap;
+
+/// A sampler descriptor without its label, with the clamps as bits to hash them.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct SamplerKey {
+    address_modes: [wgpu::AddressMode; 3],
+    filters: [wgpu::FilterMode; 3],
+    lod_clamps: [u32; 2],
+    compare: Option<wgpu::CompareFunction>,
+    anisotropy_clamp: u16,
+    border_color: Option<wgpu::SamplerBorderColor>,
+}
+
+impl SamplerKey {
+    fn new(descriptor: &wgpu::SamplerDescriptor<'_>) -> Self {
+        Self {
+            address_modes: [
+                descriptor.address_mode_u,
+                descriptor.address_mode_v,
+                descriptor.address_mode_w,
+            ],
+            filters: [
+                descriptor.mag_filter,
+                descriptor.min_filter,
+                descriptor.mipmap_filter,
+            ],
+            lod_clamps: [
+                descriptor.lod_min_clamp.to_bits(),
+                descriptor.lod_max_clamp.to_bits(),
+            ],
+            compare: descriptor.compare,
+            anisotropy_clamp: descriptor.anisotropy_clamp,
+            border_color: descriptor.border_color,
+        }
+    }
+}
+
+/// Objects shared across a device; identical descriptions get the same handle.
+/// The label of the first request is the one the object keeps.
+pub struct ResourceCache {
+    device: wgpu::Device,
+    layouts: RefCell<HashMap<Vec<wgpu::BindGroupLayoutEntry>, BindGroupLayout>>,
+    samplers: RefCell<HashMap<SamplerKey, wgpu::Sampler>>,
+    mipmap_pipelines: RefCell<HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>>,
+    placeholders: RefCell<HashMap<wgpu::TextureViewDimension, (wgpu::Texture, wgpu::TextureView)>>,
+    textures: RefCell<HashMap<(PathBuf, LoadOptions), Arc<MaterialTexture>>>,
+}
+
+impl ResourceCache {
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            device: device.clone(),
+            layouts: RefCell::new(HashMap::new()),
+            samplers: RefCell::new(HashMap::new()),
+            mipmap_pipelines: RefCell::new(HashMap::new()),
+            placeholders: RefCell::new(HashMap::new()),
+            textures: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// The order of the entries doesn't matter.
+    pub fn get_bind_group_layout(
+        &self,
+        label: &str,
+        entries: &[wgpu::BindGroupLayoutEntry],
+    ) -> BindGroupLayout {
+        let mut entries = entries.to_vec();
+        entries.sort_by_key(|entry| entry.binding);
+
+        self.layouts
+            .borrow_mut()
+            .entry(entries)
+            .or_insert_with_key(|entries| {
+                let desc = wgpu::BindGroupLayoutDescriptor {
+                    label: Some(label),
+                    entries,
+                };
+                BindGroupLayout {
+                    layout: self.device.create_bind_group_layout(&desc),
+                    entries: entries.clone(),
+                }
+            })
+            .clone()
+    }
+
+    pub fn get_sampler(&self, descriptor: &wgpu::SamplerDescriptor<'_>) -> wgpu::Sampler {
+        self.samplers
+            .borrow_mut()
+            .entry(SamplerKey::new(descriptor))
+            .or_insert_with(|| self.device.create_sampler(descriptor))
+            .clone()
+    }
+
+    pub fn get_mipmap_pipeline(&self, format: wgpu::TextureFormat) -> wgpu::RenderPipeline {
+        if let Some(pipeline) = self.mipmap_pipelines.borrow().get(&format) {
+            return pipeline.clone();
+        }
+
+        // Creating the pipeline goes back to the cache for its layout
+        let pipeline = mipmap::create_pipeline(&self.device, self, format);
+        self.mipmap_pipelines
+            .borrow_mut()
+            .insert(format, pipeline.clone());
+        pipeline
+    }
+
+    /// For bindings the shader won't sample. Arrays get two layers, as some
+    /// backends treat a single layer texture as 2D.
+    pub fn get_placeholder_texture(
+        &self,
+        dimension: wgpu::TextureViewDimension,
+    ) -> (wgpu::Texture, wgpu::TextureView) {
+        self.placeholders
+            .borrow_mut()
+            .entry(dimension)
+            .or_insert_with(|| {
+                let depth_or_array_layers = match dimension {
+                    wgpu::TextureViewDimension::D2Array => 2,
+                    wgpu::TextureViewDimension::Cube => 6,
+                    wgpu::TextureViewDimension::CubeArray => 12,
+                    _ => 1,
+                };
+                let texture_descriptor = wgpu::TextureDescriptor {
+                    label: Some("Placeholder Texture"),
+                    size: wgpu::Extent3d {
+                        width: 1,
+                        height: 1,
+                        depth_or_array_layers,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: dimension.compatible_texture_dimension(),
+                    format: wgpu::TextureFormat::Rgba8Unorm,
+                    usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                };
+                let texture = self.device.create_texture(&texture_descriptor);
+
+                let view_descriptor = wgpu::TextureViewDescriptor {
+                    dimension: Some(dimension),
+                    ..Default::default()
+                };
+                let view = texture.create_view(&view_descriptor);
+                (texture, view)
+            })
+            .clone()
+    }
+
+    /// Paths are canonicalized, so however a file is named it gets the same texture.
+    pub fn get_texture(
+        &self,
+        path: &Path,
+        options: &LoadOptions,
+        queue: &wgpu::Queue,
+    ) -> image::ImageResult<Arc<MaterialTexture>> {
+        self.get_or_upload_texture(path, options, queue, |path| {
+            let image = image_loader::decode(path)?;
+            Ok(PreparedImage::new(image, options, &self.device))
+        })
+    }
+
+    /// Only uploads the image if the cache doesn't have it yet.
+    pub fn add_texture(
+        &self,
+        path: &Path,
+        image: PreparedImage,
+        queue: &wgpu::Queue,
+    ) -> image::ImageResult<Arc<MaterialTexture>> {
+        let options = *image.get_options();
+        self.get_or_upload_texture(path, &options, queue, |_| Ok(image))
+    }
+
+    fn get_or_upload_texture<F>(
+        &self,
+        path: &Path,
+        options: &LoadOptions,
+        queue: &wgpu::Queue,
+        prepare: F,
+    ) -> image::ImageResult<Arc<MaterialTexture>>
+    where
+        F: FnOnce(&Path) -> image::ImageResult<PreparedImage>,
+    {
+        let key = (fs::canonicalize(path)?, *options);
+        if let Some(texture) = self.textures.borrow().get(&key) {
+            return Ok(texture.clone());
+        }
+
+        // No borrow is held here, as uploading asks the cache for a sampler
+        let image = prepare(&key.0)?;
+        let label = key.0.to_string_lossy().into_owned();
+        let texture = Arc::new(MaterialTexture::upload(
+            image,
+            &self.device,
+            queue,
+            self,
+            &label,
+        ));
+        self.textures.borrow_mut().insert(key, texture.clone());
+        Ok(texture)
+    }
+
+    /// Drops the textures only the cache still holds.
+    pub fn evict_unused_textures(&self) -> usize {
+        let mut textures = self.textures.borrow_mut();
+        let count = textures.len();
+        textures.retain(|_, texture| Arc::strong_count(texture) > 1);
+        count - textures.len()
+    }
+
+    pub fn get_texture_memory(&self) -> u64 {
+        self.textures
+            .borrow()
+            .values()
+            .map(|texture| texture.get_memory_size())
+            .sum()
+    }
+
+    pub fn get_texture_count(&self) -> usize {
+        self.textures.borrow().len()
+    }
+
+    pub fn get_layout_count(&self) -> usize {
+        self.layouts.borrow().len()
+    }
+
+    pub fn get_sampler_count(&self) -> usize {
+        self.samplers.borrow().len()
+    }
+}
@@ -0,0 +1,36 @@
+//! The device unit tests create their resources on: the fallback adapter with
+//! downlevel limits, like the golden tests.
+
+use std::sync::OnceLock;
+
+use futures::executor::block_on;
+
+pub fn get_test_adapter() -> &'static wgpu::Adapter {
+    static ADAPTER: OnceLock<wgpu::Adapter> = OnceLock::new();
+    ADAPTER.get_or_init(|| {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        });
+        block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            compatible_surface: None,
+            force_fallback_adapter: true,
+        }))
+        .unwrap_or_else(|err| panic!("No fallback adapter for the unit tests! {}", err))
+    })
+}
+
+pub fn get_test_device() -> &'static (wgpu::Device, wgpu::Queue) {
+    static GPU: OnceLock<(wgpu::Device, wgpu::Queue)> = OnceLock::new();
+    GPU.get_or_init(|| {
+        let device_descriptor = wgpu::DeviceDescriptor {
+            required_features: wgpu::Features::empty(),
+            required_limits: wgpu::Limits::downlevel_defaults(),
+            label: Some("Test Device"),
+            ..Default::default()
+        };
+        block_on(get_test_adapter().request_device(&device_descriptor))
+            .unwrap_or_else(|err| panic!("Can't create the test device! {}", err))
+    })
+}
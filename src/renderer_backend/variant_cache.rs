@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+
+use super::pipeline::ColorTarget;
+
+/// Everything a render pipeline variant is compiled from. Two builders with the
+/// same key produce interchangeable pipelines.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct VariantKey {
+    pub(crate) shader_filename: String,
+    pub(crate) vertex_entry: String,
+    pub(crate) fragment_entry: String,
+    pub(crate) defines: Vec<(String, String)>,
+    pub(crate) vertex_buffer_layouts: Vec<(u64, wgpu::VertexStepMode, Vec<wgpu::VertexAttribute>)>,
+    pub(crate) bind_group_layouts: Vec<wgpu::BindGroupLayout>,
+    /// Override constants, with the values as bits so they can be hashed.
+    pub(crate) constants: Vec<(String, u64)>,
+    pub(crate) push_constant_ranges: Vec<wgpu::PushConstantRange>,
+    pub(crate) color_targets: Vec<ColorTarget>,
+    pub(crate) primitive: wgpu::PrimitiveState,
+    pub(crate) depth_stencil: Option<wgpu::DepthStencilState>,
+    pub(crate) multisample: wgpu::MultisampleState,
+}
+
+/// Compiled render pipeline permutations, keyed by what they were built from.
+#[derive(Default)]
+pub struct VariantCache {
+    pipelines: HashMap<VariantKey, wgpu::RenderPipeline>,
+}
+
+impl VariantCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pipelines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pipelines.is_empty()
+    }
+
+    /// Drops every cached variant, e.g. after a shader changed on disk.
+    pub fn clear(&mut self) {
+        self.pipelines.clear();
+    }
+
+    pub(crate) fn get(&self, key: &VariantKey) -> Option<&wgpu::RenderPipeline> {
+        self.pipelines.get(key)
+    }
+
+    pub(crate) fn insert(&mut self, key: VariantKey, pipeline: wgpu::RenderPipeline) {
+        self.pipelines.insert(key, pipeline);
+    }
+}
@@ -0,0 +1,582 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use glm::{vec2, Vec2};
+use image::RgbaImage;
+
+use super::bind_group_layout::BindGroupLayout;
+use super::material::{LoadOptions, Material, TILE_SIZE};
+use super::mesh_builder::{self, Mesh};
+use super::push_constants::PushConstants;
+use super::resource_cache::ResourceCache;
+
+/// A level is only drawn while it is at least half its size on screen, so even
+/// a 4K window sees about 60 tiles.
+pub const MAX_RESIDENT_TILES: usize = 64;
+pub const MAX_UPLOADS_PER_FRAME: usize = 4;
+/// Relative to fitting the whole image, and in window pixels per image pixel.
+const MIN_ZOOM: f32 = 0.5;
+const MAX_SCALE: f32 = 32.0;
+
+pub struct Camera {
+    image_size: Vec2,
+    window_size: Vec2,
+    center: Vec2,
+    scale: f32,
+}
+
+impl Camera {
+    pub fn new(image_size: (u32, u32), window_size: (u32, u32)) -> Self {
+        let mut camera = Self {
+            image_size: vec2(image_size.0 as f32, image_size.1 as f32),
+            window_size: vec2(window_size.0 as f32, window_size.1 as f32),
+            center: Vec2::new(0.0, 0.0),
+            scale: 1.0,
+        };
+        camera.fit();
+        camera
+    }
+
+    pub fn fit(&mut self) {
+        self.center = self.image_size * 0.5;
+        self.scale = self.get_fit_scale();
+    }
+
+    pub fn resize(&mut self, window_size: (u32, u32)) {
+        self.window_size = vec2(window_size.0 as f32, window_size.1 as f32);
+    }
+
+    /// Keeps the image pixel under `cursor` in place.
+    pub fn zoom_at(&mut self, cursor: Vec2, factor: f32) {
+        let anchor = self.to_image(cursor);
+        self.scale = (self.scale * factor).clamp(
+            self.get_fit_scale() * MIN_ZOOM,
+            MAX_SCALE.max(self.get_fit_scale()),
+        );
+        self.center = anchor - (cursor - self.window_size * 0.5) / self.scale;
+    }
+
+    pub fn pan(&mut self, delta: Vec2) {
+        self.center = self.center - delta / self.scale;
+    }
+
+    pub fn get_scale(&self) -> f32 {
+        self.scale
+    }
+
+    fn get_fit_scale(&self) -> f32 {
+        let scales = self.window_size / self.image_size;
+        scales.x.min(scales.y)
+    }
+
+    fn to_image(&self, window: Vec2) -> Vec2 {
+        (window - self.window_size * 0.5) / self.scale + self.center
+    }
+
+    fn to_window(&self, image: Vec2) -> Vec2 {
+        (image - self.center) * self.scale + self.window_size * 0.5
+    }
+
+    fn get_visible_rect(&self) -> (Vec2, Vec2) {
+        let zero = Vec2::new(0.0, 0.0);
+        let top_left = glm::clamp(self.to_image(zero), zero, self.image_size);
+        let bottom_right = glm::clamp(self.to_image(self.window_size), zero, self.image_size);
+        (top_left, bottom_right)
+    }
+
+    fn to_clip(&self, top_left: Vec2, bottom_right: Vec2) -> [f32; 4] {
+        let top_left = self.to_window(top_left) / self.window_size;
+        let bottom_right = self.to_window(bottom_right) / self.window_size;
+        [
+            top_left.x * 2.0 - 1.0,
+            1.0 - top_left.y * 2.0,
+            bottom_right.x * 2.0 - 1.0,
+            1.0 - bottom_right.y * 2.0,
+        ]
+    }
+}
+
+/// The image downscaled by 2^level, in tiles numbered row by row.
+struct Level {
+    image: RgbaImage,
+    columns: u32,
+    rows: u32,
+}
+
+impl Level {
+    fn get_tile_rect(&self, index: usize, tile_size: u32) -> (u32, u32, u32, u32) {
+        let (width, height) = self.image.dimensions();
+        let x = index as u32 % self.columns * tile_size;
+        let y = index as u32 / self.columns * tile_size;
+        (x, y, tile_size.min(width - x), tile_size.min(height - y))
+    }
+}
+
+struct ResidentTile {
+    material: Material,
+    last_used: u64,
+}
+
+/// The whole image stays in memory, but only the visible tiles of the level
+/// matching the zoom are on the GPU, over the coarsest level. Draw with a
+/// pipeline on `shaders/viewer.wgsl`.
+pub struct ImageViewer {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    layout: BindGroupLayout,
+    quad_mesh: Mesh,
+    draw_params: PushConstants,
+    tile_size: u32,
+    levels: Vec<Level>,
+    camera: Camera,
+    resident: HashMap<(usize, usize), ResidentTile>,
+    frame: u64,
+}
+
+impl ImageViewer {
+    pub fn open(
+        path: &Path,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &BindGroupLayout,
+        window_size: (u32, u32),
+    ) -> image::ImageResult<Self> {
+        let mut reader = image::ImageReader::open(path)?.with_guessed_format()?;
+        reader.no_limits();
+        let image = reader.decode()?.into_rgba8();
+
+        let tile_size = TILE_SIZE.min(device.limits().max_texture_dimension_2d);
+        let viewer = Self::new(image, tile_size, device, queue, layout, window_size);
+        log::info!(
+            "Opened {} as {} levels of {} tiles at most",
+            path.display(),
+            viewer.levels.len(),
+            viewer.levels[0].columns * viewer.levels[0].rows
+        );
+        Ok(viewer)
+    }
+
+    fn new(
+        image: RgbaImage,
+        tile_size: u32,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &BindGroupLayout,
+        window_size: (u32, u32),
+    ) -> Self {
+        let camera = Camera::new(image.dimensions(), window_size);
+        let levels = Self::build_levels(image, tile_size);
+
+        // The coarsest tile and every resident tile can be drawn in one frame
+        let draw_params = PushConstants::new(
+            device,
+            wgpu::ShaderStages::VERTEX,
+            std::mem::size_of::<[f32; 4]>() as u32,
+            1,
+            MAX_RESIDENT_TILES as u32 + 1,
+        );
+
+        Self {
+            device: device.clone(),
+            queue: queue.clone(),
+            layout: layout.clone(),
+            quad_mesh: mesh_builder::make_quad(device),
+            draw_params,
+            tile_size,
+            levels,
+            camera,
+            resident: HashMap::new(),
+            frame: 0,
+        }
+    }
+
+    fn build_levels(image: RgbaImage, tile_size: u32) -> Vec<Level> {
+        let mut levels: Vec<Level> = vec![];
+        let mut image = Some(image);
+        while let Some(level_image) = image.take() {
+            let (width, height) = level_image.dimensions();
+            if width > tile_size || height > tile_size {
+                image = Some(image::imageops::resize(
+                    &level_image,
+                    width.div_ceil(2),
+                    height.div_ceil(2),
+                    image::imageops::FilterType::Triangle,
+                ));
+            }
+            levels.push(Level {
+                image: level_image,
+                columns: width.div_ceil(tile_size),
+                rows: height.div_ceil(tile_size),
+            });
+        }
+        levels
+    }
+
+    pub fn get_camera_mut(&mut self) -> &mut Camera {
+        &mut self.camera
+    }
+
+    pub fn get_draw_params(&self) -> &PushConstants {
+        &self.draw_params
+    }
+
+    pub fn get_resident_count(&self) -> usize {
+        self.resident.len()
+    }
+
+    fn get_level(&self) -> usize {
+        let level = (1.0 / self.camera.get_scale()).log2().floor().max(0.0) as usize;
+        level.min(self.levels.len() - 1)
+    }
+
+    fn get_visible_tiles(&self, level: usize) -> Vec<usize> {
+        let Level {
+            image,
+            columns,
+            rows,
+        } = &self.levels[level];
+        let (top_left, bottom_right) = self.camera.get_visible_rect();
+        let to_level = vec2(image.width() as f32, image.height() as f32) / self.camera.image_size;
+        let first = top_left * to_level / self.tile_size as f32;
+        let last = bottom_right * to_level / self.tile_size as f32;
+
+        let mut visible: Vec<usize> = vec![];
+        for row in first.y as u32..(last.y.ceil() as u32).min(*rows) {
+            for column in first.x as u32..(last.x.ceil() as u32).min(*columns) {
+                visible.push((row * columns + column) as usize);
+            }
+        }
+        visible.truncate(MAX_RESIDENT_TILES);
+        visible
+    }
+
+    /// Returns true while tiles are still missing.
+    pub fn update(&mut self, resource_cache: &ResourceCache) -> bool {
+        self.frame += 1;
+        let coarsest = self.levels.len() - 1;
+        let level = self.get_level();
+        let wanted: Vec<(usize, usize)> = std::iter::once((coarsest, 0))
+            .chain(
+                self.get_visible_tiles(level)
+                    .into_iter()
+                    .map(|index| (level, index)),
+            )
+            .collect();
+
+        let mut uploads = 0;
+        let mut missing = false;
+        for key in wanted {
+            if let Some(tile) = self.resident.get_mut(&key) {
+                tile.last_used = self.frame;
+                continue;
+            }
+            if uploads == MAX_UPLOADS_PER_FRAME {
+                missing = true;
+                continue;
+            }
+
+            let level = &self.levels[key.0];
+            let (x, y, width, height) = level.get_tile_rect(key.1, self.tile_size);
+            let image = image::imageops::crop_imm(&level.image, x, y, width, height).to_image();
+            // Clamped, so tiles don't wrap to their opposite edge and leave seams
+            let material = Material::from_image(
+                image,
+                &LoadOptions {
+                    address_mode: wgpu::AddressMode::ClampToEdge,
+                    ..Default::default()
+                },
+                &self.device,
+                &self.queue,
+                resource_cache,
+                &format!("Viewer Tile {}/{}", key.0, key.1),
+                &self.layout,
+            );
+            let tile = ResidentTile {
+                material,
+                last_used: self.frame,
+            };
+            self.resident.insert(key, tile);
+            uploads += 1;
+        }
+
+        self.evict();
+        missing
+    }
+
+    fn evict(&mut self) {
+        let coarsest = self.levels.len() - 1;
+        while self.resident.len() > MAX_RESIDENT_TILES {
+            let oldest = self
+                .resident
+                .iter()
+                .filter(|(key, tile)| key.0 != coarsest && tile.last_used < self.frame)
+                .min_by_key(|(_, tile)| tile.last_used)
+                .map(|(key, _)| *key);
+            match oldest {
+                Some(key) => self.resident.remove(&key),
+                None => return,
+            };
+        }
+    }
+
+    /// Call `get_draw_params().flush` before submitting.
+    pub fn draw(&self, renderpass: &mut wgpu::RenderPass<'_>) {
+        let offset = self.quad_mesh.offset;
+        renderpass.set_vertex_buffer(0, self.quad_mesh.buffer.slice(..offset));
+        renderpass.set_index_buffer(
+            self.quad_mesh.buffer.slice(offset..),
+            wgpu::IndexFormat::Uint16,
+        );
+
+        let coarsest = self.levels.len() - 1;
+        let level = self.get_level();
+        let mut keys = vec![(coarsest, 0)];
+        if level != coarsest {
+            keys.extend(
+                self.get_visible_tiles(level)
+                    .into_iter()
+                    .map(|index| (level, index)),
+            );
+        }
+
+        for key in keys {
+            let Some(tile) = self.resident.get(&key) else {
+                continue;
+            };
+            let level = &self.levels[key.0];
+            let (x, y, width, height) = level.get_tile_rect(key.1, self.tile_size);
+            let (level_width, level_height) = level.image.dimensions();
+            let to_image = self.camera.image_size / vec2(level_width as f32, level_height as f32);
+            let top_left = vec2(x as f32, y as f32) * to_image;
+            let bottom_right = vec2((x + width) as f32, (y + height) as f32) * to_image;
+            let rect = self.camera.to_clip(top_left, bottom_right);
+
+            renderpass.set_bind_group(0, &tile.material.bind_group, &[]);
+            self.draw_params.set(renderpass, bytemuck::bytes_of(&rect));
+            renderpass.draw_indexed(0..6, 0, 0..1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer_backend::bind_group_layout;
+    use crate::renderer_backend::test_device::get_test_device;
+
+    fn assert_near(actual: Vec2, expected: Vec2) {
+        assert!(
+            glm::distance(actual, expected) < 1e-3,
+            "{:?} is not {:?}",
+            actual,
+            expected
+        );
+    }
+
+    fn create_layout(resource_cache: &ResourceCache) -> BindGroupLayout {
+        let mut builder = bind_group_layout::Builder::new(&get_test_device().0);
+        builder.add_material(0);
+        builder.build_cached("Test Material Layout", resource_cache)
+    }
+
+    fn create_viewer(
+        image_size: (u32, u32),
+        tile_size: u32,
+        window_size: (u32, u32),
+        resource_cache: &ResourceCache,
+    ) -> ImageViewer {
+        let (device, queue) = get_test_device();
+        let layout = create_layout(resource_cache);
+        let image = RgbaImage::new(image_size.0, image_size.1);
+        ImageViewer::new(image, tile_size, device, queue, &layout, window_size)
+    }
+
+    fn create_tile(
+        viewer: &ImageViewer,
+        resource_cache: &ResourceCache,
+        last_used: u64,
+    ) -> ResidentTile {
+        let material = Material::from_image(
+            RgbaImage::new(1, 1),
+            &LoadOptions::default(),
+            &viewer.device,
+            &viewer.queue,
+            resource_cache,
+            "Test Tile",
+            &viewer.layout,
+        );
+        ResidentTile {
+            material,
+            last_used,
+        }
+    }
+
+    /// Just the header of a farbfeld image, without its pixels.
+    fn write_image_header(name: &str, width: u32, height: u32) -> std::path::PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("learn_wgpu-viewer-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("image.ff");
+        let mut header = b"farbfeld".to_vec();
+        header.extend(width.to_be_bytes());
+        header.extend(height.to_be_bytes());
+        std::fs::write(&path, header).unwrap();
+        path
+    }
+
+    #[test]
+    fn open_takes_images_over_the_default_limits() {
+        let (device, queue) = get_test_device();
+        let resource_cache = ResourceCache::new(device);
+        let layout = create_layout(&resource_cache);
+
+        // 2 GiB of pixels is over `image`'s allocation limit, so only the missing
+        // pixels fail the decode
+        let path = write_image_header("over_the_default_limits", 16384, 16384);
+        let result = ImageViewer::open(&path, device, queue, &layout, (64, 64));
+        assert!(matches!(result, Err(image::ImageError::IoError(_))));
+    }
+
+    #[test]
+    fn zoom_keeps_the_point_under_the_cursor() {
+        let mut camera = Camera::new((256, 128), (64, 32));
+        let cursor = vec2(10.0, 5.0);
+        let anchor = camera.to_image(cursor);
+
+        camera.zoom_at(cursor, 3.0);
+        assert_eq!(camera.get_scale(), 0.75);
+        assert_near(camera.to_image(cursor), anchor);
+
+        camera.zoom_at(vec2(50.0, 20.0), 0.5);
+        camera.zoom_at(cursor, 2.0);
+        assert_near(camera.to_window(camera.to_image(cursor)), cursor);
+    }
+
+    #[test]
+    fn zoom_is_clamped() {
+        let mut camera = Camera::new((256, 128), (64, 32));
+        let cursor = vec2(60.0, 2.0);
+        let anchor = camera.to_image(cursor);
+
+        camera.zoom_at(cursor, 1000.0);
+        assert_eq!(camera.get_scale(), MAX_SCALE);
+        assert_near(camera.to_image(cursor), anchor);
+
+        camera.zoom_at(cursor, 0.0001);
+        assert_eq!(camera.get_scale(), 0.25 * MIN_ZOOM);
+    }
+
+    #[test]
+    fn tiles_cover_each_level() {
+        let resource_cache = ResourceCache::new(&get_test_device().0);
+        // Levels of 100x70, 50x35 and 25x18
+        let viewer = create_viewer((100, 70), 32, (64, 32), &resource_cache);
+        let grids: Vec<(u32, u32)> = viewer
+            .levels
+            .iter()
+            .map(|level| (level.columns, level.rows))
+            .collect();
+        assert_eq!(grids, [(4, 3), (2, 2), (1, 1)]);
+
+        // Tiles on the right and bottom edges are cut short
+        assert_eq!(viewer.levels[0].get_tile_rect(0, 32), (0, 0, 32, 32));
+        assert_eq!(viewer.levels[0].get_tile_rect(11, 32), (96, 64, 4, 6));
+        assert_eq!(viewer.levels[1].get_tile_rect(3, 32), (32, 32, 18, 3));
+        assert_eq!(viewer.levels[2].get_tile_rect(0, 32), (0, 0, 25, 18));
+    }
+
+    #[test]
+    fn level_follows_the_scale() {
+        let resource_cache = ResourceCache::new(&get_test_device().0);
+        // Levels of 256x128, 128x64, 64x32 and 32x16, fitted at 1/4
+        let mut viewer = create_viewer((256, 128), 32, (64, 32), &resource_cache);
+        assert_eq!(viewer.levels.len(), 4);
+        assert_eq!(viewer.get_level(), 2);
+
+        let center = vec2(32.0, 16.0);
+        viewer.camera.zoom_at(center, 1.5);
+        assert_eq!(viewer.get_level(), 1);
+        viewer.camera.zoom_at(center, 4.0 / 3.0);
+        assert_eq!(viewer.get_level(), 1);
+        viewer.camera.zoom_at(center, 1.5);
+        assert_eq!(viewer.get_level(), 0);
+        viewer.camera.zoom_at(center, 8.0);
+        assert_eq!(viewer.get_level(), 0);
+
+        viewer.camera.fit();
+        viewer.camera.zoom_at(center, 0.5);
+        assert_eq!(viewer.get_level(), 3);
+    }
+
+    #[test]
+    fn visible_tiles() {
+        let resource_cache = ResourceCache::new(&get_test_device().0);
+        let mut viewer = create_viewer((256, 128), 32, (64, 32), &resource_cache);
+        assert_eq!(viewer.get_visible_tiles(2), [0, 1]);
+        assert_eq!(viewer.get_visible_tiles(0).len(), 32);
+
+        // 1:1 in the middle shows image pixels 96 to 160 across and 48 to 80
+        // down, tile columns 3 and 4 of rows 1 and 2 in the 8x4 grid, and
+        // columns 1 and 2 of both rows in the 4x2 grid of the next level
+        viewer.camera.zoom_at(vec2(32.0, 16.0), 4.0);
+        assert_eq!(viewer.get_visible_tiles(0), [11, 12, 19, 20]);
+        assert_eq!(viewer.get_visible_tiles(1), [1, 2, 5, 6]);
+
+        // Panned past the bottom right corner only the last tile is left
+        viewer.camera.pan(vec2(-140.0, -60.0));
+        assert_eq!(viewer.get_visible_tiles(0), [31]);
+    }
+
+    #[test]
+    fn visible_tiles_are_capped() {
+        let resource_cache = ResourceCache::new(&get_test_device().0);
+        let viewer = create_viewer((256, 256), 16, (64, 64), &resource_cache);
+        assert_eq!(viewer.get_visible_tiles(0).len(), MAX_RESIDENT_TILES);
+        assert_eq!(viewer.get_visible_tiles(1), (0..64).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn evict_drops_the_least_recently_drawn() {
+        let resource_cache = ResourceCache::new(&get_test_device().0);
+        let mut viewer = create_viewer((256, 256), 16, (64, 64), &resource_cache);
+        let coarsest = viewer.levels.len() - 1;
+        viewer.frame = 100;
+
+        // The coarsest tile is the oldest and the first few are drawn this frame
+        let tile = create_tile(&viewer, &resource_cache, 0);
+        viewer.resident.insert((coarsest, 0), tile);
+        for index in 0..MAX_RESIDENT_TILES + 6 {
+            let last_used = if index < 4 { 100 } else { index as u64 };
+            let tile = create_tile(&viewer, &resource_cache, last_used);
+            viewer.resident.insert((0, index), tile);
+        }
+
+        viewer.evict();
+
+        assert_eq!(viewer.get_resident_count(), MAX_RESIDENT_TILES);
+        assert!(viewer.resident.contains_key(&(coarsest, 0)));
+        for index in 0..MAX_RESIDENT_TILES + 6 {
+            let dropped = (4..11).contains(&index);
+            assert_eq!(
+                !viewer.resident.contains_key(&(0, index)),
+                dropped,
+                "{index}"
+            );
+        }
+    }
+
+    #[test]
+    fn evict_keeps_what_this_frame_draws() {
+        let resource_cache = ResourceCache::new(&get_test_device().0);
+        let mut viewer = create_viewer((256, 256), 16, (64, 64), &resource_cache);
+        viewer.frame = 7;
+
+        for index in 0..MAX_RESIDENT_TILES + 2 {
+            let tile = create_tile(&viewer, &resource_cache, 7);
+            viewer.resident.insert((0, index), tile);
+        }
+
+        viewer.evict();
+        assert_eq!(viewer.get_resident_count(), MAX_RESIDENT_TILES + 2);
+    }
+}
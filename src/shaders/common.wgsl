@@ -0,0 +1,16 @@
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec3<f32>,
+}
+
+struct VertexPayload {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+    @location(1) texCoord: vec2<f32>,
+    // Picking ID of the draw, passed in as its instance index.
+    @location(2) @interpolate(flat) objectId: u32,
+#ifdef BINDLESS
+    // Index of the draw's texture in the material binding array.
+    @location(3) @interpolate(flat) materialIndex: u32,
+#endif
+}
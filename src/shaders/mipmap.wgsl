@@ -0,0 +1,26 @@
+// Renders a mip level from the previous one. Sampling halfway between texels
+// with a linear filter averages each 2x2 block; sRGB views decode before and
+// encode after, so the average is taken on linear values.
+
+@group(0) @binding(0) var source: texture_2d<f32>;
+@group(0) @binding(1) var sourceSampler: sampler;
+
+struct BlitPayload {
+    @builtin(position) position: vec4<f32>,
+    @location(0) texCoord: vec2<f32>,
+}
+
+// One triangle covering the whole target.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> BlitPayload {
+    let corner = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out = BlitPayload();
+    out.position = vec4<f32>(corner * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.texCoord = corner;
+    return out;
+}
+
+@fragment
+fn fs_main(in: BlitPayload) -> @location(0) vec4<f32> {
+    return textureSample(source, sourceSampler, in.texCoord);
+}
@@ -1,37 +1,94 @@
+#include "common.wgsl"
+
+#ifdef BINDLESS
+#ifndef MATERIAL_COUNT
+#define MATERIAL_COUNT 1
+#endif
+@group(0) @binding(0) var myTextures: binding_array<texture_2d<f32>, MATERIAL_COUNT>;
+@group(0) @binding(1) var mySampler: sampler;
+#else
 @group(0) @binding(0) var myTexture: texture_2d<f32>;
 @group(0) @binding(1) var mySampler: sampler;
 
-struct Vertex {
-    @location(0) position: vec3<f32>,
-    @location(1) color: vec3<f32>,
+// Images too large for one texture are split into tiles, one per layer of
+// `myTiles`. `size` is the image's size in tiles and `columns` the number of
+// tiles in a row, or 0 for an untiled image in `myTexture`.
+struct TileGrid {
+    size: vec2<f32>,
+    columns: f32,
+}
+@group(0) @binding(2) var<uniform> tileGrid: TileGrid;
+@group(0) @binding(3) var myTiles: texture_2d_array<f32>;
+
+// Samples the image from whichever texture holds it, wrapping the UV. Gradients
+// are taken before the split so mip selection doesn't jump at tile borders.
+fn sampleMaterial(texCoord: vec2<f32>) -> vec4<f32> {
+    if (tileGrid.columns == 0.0) {
+        return textureSample(myTexture, mySampler, texCoord);
+    }
+
+    let scaled = texCoord * tileGrid.size;
+    let position = fract(texCoord) * tileGrid.size;
+    let tile = min(floor(position), ceil(tileGrid.size) - 1.0);
+    let layer = u32(tile.y * tileGrid.columns + tile.x);
+    return textureSampleGrad(myTiles, mySampler, position - tile, layer, dpdx(scaled), dpdy(scaled));
 }
+#endif
 
-struct VertexPayload {
-    @builtin(position) position: vec4<f32>,
-    @location(0) color: vec3<f32>,
-    @location(1) texCoord: vec2<f32>,
+struct DrawParams {
+    tint: vec4<f32>,
 }
 
+#ifdef PUSH_CONSTANTS
+var<push_constant> draw: DrawParams;
+#else
+@group(1) @binding(0) var<uniform> draw: DrawParams;
+#endif
+
+struct GBufferOutput {
+    @location(0) color: vec4<f32>,
+    @location(1) objectId: u32,
+}
 
 @vertex
-fn vs_main(vertex: Vertex) -> VertexPayload {
+fn vs_main(
+    vertex: Vertex,
+#ifdef BINDLESS
+    @location(2) material: u32,
+#endif
+    @builtin(instance_index) instance: u32,
+) -> VertexPayload {
     var out = VertexPayload();
     out.position = vec4<f32>(vertex.position,1.0);
     out.color = vertex.color;
     out.texCoord = vec2<f32>(0.5*(vertex.position.x + 1), -0.5 * (vertex.position.y + 1));
+    out.objectId = instance;
+#ifdef BINDLESS
+    out.materialIndex = material;
+#endif
     return out;
 }
 
+fn shade(in: VertexPayload) -> vec4<f32> {
+#ifdef VERTEX_COLOR_ONLY
+    return vec4<f32>(in.color, 1.0) * draw.tint;
+#else
+#ifdef BINDLESS
+    let texel = textureSample(myTextures[in.materialIndex], mySampler, in.texCoord);
+#else
+    let texel = sampleMaterial(in.texCoord);
+#endif
+    return vec4<f32>(in.color, 1.0) * texel * draw.tint;
+#endif
+}
+
 @fragment
 fn fs_main(in: VertexPayload) -> @location(0) vec4<f32> {
-    return vec4<f32>(in.color, 1.0) * textureSample(myTexture, mySampler, in.texCoord);
+    return shade(in);
 }
 
-@group(0) @binding(0)
-var<storage, read_write> data: array<f32>;
-
-@compute @workgroup_size(1)
-fn computeSomething(@builtin(global_invocation_id) id: vec3u) {
-    let i = id.x;
-    data[i] = data[i] * 2.0;
+// Writes the color and the object ID into two targets at once, for picking.
+@fragment
+fn fs_object_id(in: VertexPayload) -> GBufferOutput {
+    return GBufferOutput(shade(in), in.objectId);
 }
@@ -0,0 +1,39 @@
+// Draws one tile of the image viewer. The quad from `make_quad` is stretched
+// over the tile's rectangle, given in clip space per draw.
+#include "common.wgsl"
+
+@group(0) @binding(0) var tile: texture_2d<f32>;
+@group(0) @binding(1) var tileSampler: sampler;
+
+struct TileParams {
+    // Top left and bottom right corners in clip space.
+    rect: vec4<f32>,
+}
+
+#ifdef PUSH_CONSTANTS
+var<push_constant> params: TileParams;
+#else
+@group(1) @binding(0) var<uniform> params: TileParams;
+#endif
+
+struct TilePayload {
+    @builtin(position) position: vec4<f32>,
+    @location(0) texCoord: vec2<f32>,
+}
+
+@vertex
+fn vs_main(vertex: Vertex) -> TilePayload {
+    // 0 or 1 for each corner of the quad, whatever its size
+    let corner = step(vec2<f32>(0.0), vertex.position.xy);
+    let texCoord = vec2<f32>(corner.x, 1.0 - corner.y);
+
+    var out = TilePayload();
+    out.position = vec4<f32>(mix(params.rect.xy, params.rect.zw, texCoord), 0.0, 1.0);
+    out.texCoord = texCoord;
+    return out;
+}
+
+@fragment
+fn fs_main(in: TilePayload) -> @location(0) vec4<f32> {
+    return textureSample(tile, tileSampler, in.texCoord);
+}
@@ -0,0 +1,35 @@
+// Wireframe fallback for adapters without PolygonMode::Line. Draws a plain,
+// non-indexed triangle list and derives each corner's barycentric coordinate
+// from its vertex index; fragments away from an edge are discarded.
+#include "common.wgsl"
+
+// Width of the lines in pixels, set per pipeline.
+override line_width: f32 = 1.5;
+
+struct WireframePayload {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+    @location(1) barycentric: vec3<f32>,
+}
+
+@vertex
+fn vs_main(vertex: Vertex, @builtin(vertex_index) index: u32) -> WireframePayload {
+    var out = WireframePayload();
+    out.position = vec4<f32>(vertex.position, 1.0);
+    out.color = vertex.color;
+    out.barycentric = vec3<f32>(0.0);
+    out.barycentric[index % 3u] = 1.0;
+    return out;
+}
+
+@fragment
+fn fs_main(in: WireframePayload) -> @location(0) vec4<f32> {
+    // Distance to the nearest edge, measured in pixels.
+    let width = fwidth(in.barycentric);
+    let edge = smoothstep(vec3<f32>(0.0), width * line_width, in.barycentric);
+    let coverage = 1.0 - min(min(edge.x, edge.y), edge.z);
+    if coverage < 0.1 {
+        discard;
+    }
+    return vec4<f32>(in.color, 1.0);
+}
@@ -0,0 +1,1076 @@
+//! Golden-image regression tests.
+//!
+//! Every test renders a named scene offscreen on the fallback adapter and compares it
+//! pixel by pixel with `tests/golden/<name>.png`. On a mismatch the rendered frame and a
+//! diff image (mismatched pixels in red) are written to `target/golden/`.
+//!
+//! Regenerate the references after an intended visual change with:
+//! ```bash
+//! GOLDEN_UPDATE=1 cargo test --test golden
+//! ```
+
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+
+use futures::executor::block_on;
+use image::imageops::FilterType;
+use image::{Rgba, RgbaImage};
+use learn_wgpu::renderer_backend::bindless::{self, BindlessMaterials};
+use learn_wgpu::renderer_backend::blend::BlendMode;
+use learn_wgpu::renderer_backend::depth_texture::DepthTexture;
+use learn_wgpu::renderer_backend::material::{LoadOptions, Material};
+use learn_wgpu::renderer_backend::multisample::MultisampledTexture;
+use learn_wgpu::renderer_backend::offscreen::OffscreenTarget;
+use learn_wgpu::renderer_backend::pipeline::RenderPipelineBuilder;
+use learn_wgpu::renderer_backend::push_constants::PushConstants;
+use learn_wgpu::renderer_backend::resource_cache::ResourceCache;
+use learn_wgpu::renderer_backend::variant_cache::VariantCache;
+use learn_wgpu::renderer_backend::{bind_group_layout, mesh_builder, mipmap};
+use wgpu::util::DeviceExt;
+
+const WIDTH: u32 = 256;
+const HEIGHT: u32 = 256;
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const TINT: [f32; 4] = [1.0, 0.5, 0.25, 1.0];
+const TRANSLUCENT: [f32; 4] = [1.0, 1.0, 1.0, 0.5];
+const ID_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Uint;
+const QUAD_ID: u32 = 1;
+const TRIANGLE_ID: u32 = 2;
+const SAMPLE_COUNT: u32 = 4;
+
+struct Tolerance {
+    /// Largest per-channel difference for a pixel to still count as matching.
+    channel: u8,
+    /// Fraction of pixels allowed to mismatch before the test fails.
+    mismatched_pixels: f32,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            channel: 2,
+            mismatched_pixels: 0.001,
+        }
+    }
+}
+
+struct Resources {
+    render_pipeline: wgpu::RenderPipeline,
+    vertex_color_pipeline: wgpu::RenderPipeline,
+    /// Writes color and object IDs into two targets.
+    object_id_pipeline: wgpu::RenderPipeline,
+    alpha_blend_pipeline: wgpu::RenderPipeline,
+    additive_pipeline: wgpu::RenderPipeline,
+    /// Renders with `SAMPLE_COUNT` samples, into a multisampled target.
+    multisampled_pipeline: wgpu::RenderPipeline,
+    /// Tests and writes depth, into a `DepthTexture`.
+    depth_pipeline: wgpu::RenderPipeline,
+    /// Culls no faces, where the others cull back faces.
+    unculled_pipeline: wgpu::RenderPipeline,
+    /// Draws triangle strips.
+    strip_pipeline: wgpu::RenderPipeline,
+    triangle_mesh: wgpu::Buffer,
+    quad_mesh: mesh_builder::Mesh,
+    /// The triangle in front of the quad, and the quad behind it as a triangle list.
+    near_triangle_mesh: wgpu::Buffer,
+    far_quad_mesh: wgpu::Buffer,
+    /// The triangle wound clockwise, so facing away.
+    back_facing_triangle_mesh: wgpu::Buffer,
+    /// The quad as a triangle strip.
+    quad_strip_mesh: wgpu::Buffer,
+    triangle_material: Material,
+    quad_material: Material,
+    /// Without `Features::PUSH_CONSTANTS` on the golden device, this exercises the
+    /// uniform fallback.
+    draw_params: PushConstants,
+    /// The quad's and the triangle's textures in one array, and the pipeline
+    /// that indexes it, where the adapter supports bindless materials.
+    bindless: Option<(BindlessMaterials, wgpu::RenderPipeline)>,
+    resource_cache: ResourceCache,
+    material_bind_group_layout: bind_group_layout::BindGroupLayout,
+}
+
+type Draw = fn(&mut wgpu::RenderPass<'_>, &Resources);
+
+/// The fallback adapter's device. Panics if there is none, so a broken adapter
+/// setup fails the suite instead of passing it untested.
+fn get_gpu() -> &'static (wgpu::Device, wgpu::Queue) {
+    static GPU: OnceLock<(wgpu::Device, wgpu::Queue)> = OnceLock::new();
+    GPU.get_or_init(|| {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        });
+        let adapter = block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            compatible_surface: None,
+            force_fallback_adapter: true,
+        }))
+        .unwrap_or_else(|err| panic!("No fallback adapter for the golden tests! {}", err));
+        // Bindless materials are tested where the adapter has them
+        let device_descriptor = wgpu::DeviceDescriptor {
+            required_features: adapter.features() & bindless::FEATURES,
+            required_limits: wgpu::Limits {
+                max_binding_array_elements_per_shader_stage: adapter
+                    .limits()
+                    .max_binding_array_elements_per_shader_stage,
+                ..wgpu::Limits::downlevel_defaults()
+            },
+            label: Some("Golden Device"),
+            ..Default::default()
+        };
+        block_on(adapter.request_device(&device_descriptor))
+            .unwrap_or_else(|err| panic!("Can't create the golden device! {}", err))
+    })
+}
+
+fn create_material_layout(
+    device: &wgpu::Device,
+    resource_cache: &ResourceCache,
+) -> bind_group_layout::BindGroupLayout {
+    let mut builder = bind_group_layout::Builder::new(device);
+    builder.add_material(0);
+    builder.build_cached("Material Bind Group Layout", resource_cache)
+}
+
+fn create_resources(device: &wgpu::Device, queue: &wgpu::Queue) -> Resources {
+    let draw_params = PushConstants::new(device, wgpu::ShaderStages::FRAGMENT, 16, 1, 4);
+
+    let resource_cache = ResourceCache::new(device);
+    let material_bind_group_layout = create_material_layout(device, &resource_cache);
+
+    let render_pipeline: wgpu::RenderPipeline;
+    {
+        let mut builder = RenderPipelineBuilder::new(device);
+        builder.set_shader_module("shaders/shader.wgsl", "vs_main", "fs_main");
+        builder.set_pixel_format(FORMAT);
+        builder.add_vertex_buffer_layout(mesh_builder::Vertex::get_layout());
+        builder.add_bind_group_layout(&material_bind_group_layout);
+        builder.set_push_constants(&draw_params);
+        render_pipeline = builder.build("Render Pipeline").unwrap();
+    }
+
+    let vertex_color_pipeline: wgpu::RenderPipeline;
+    {
+        let mut builder = RenderPipelineBuilder::new(device);
+        builder.set_shader_module("shaders/shader.wgsl", "vs_main", "fs_main");
+        builder.add_feature("VERTEX_COLOR_ONLY");
+        builder.set_pixel_format(FORMAT);
+        builder.add_vertex_buffer_layout(mesh_builder::Vertex::get_layout());
+        builder.add_bind_group_layout(&material_bind_group_layout);
+        builder.set_push_constants(&draw_params);
+        vertex_color_pipeline = builder.build("Vertex Color Pipeline").unwrap();
+    }
+
+    let object_id_pipeline: wgpu::RenderPipeline;
+    {
+        let mut builder = RenderPipelineBuilder::new(device);
+        builder.set_shader_module("shaders/shader.wgsl", "vs_main", "fs_object_id");
+        builder.set_pixel_format(FORMAT);
+        builder.add_color_target(ID_FORMAT, BlendMode::Opaque, wgpu::ColorWrites::ALL);
+        builder.add_vertex_buffer_layout(mesh_builder::Vertex::get_layout());
+        builder.add_bind_group_layout(&material_bind_group_layout);
+        builder.set_push_constants(&draw_params);
+        object_id_pipeline = builder.build("Object ID Pipeline").unwrap();
+    }
+
+    let blend_pipelines = [BlendMode::AlphaBlend, BlendMode::Additive].map(|blend_mode| {
+        let mut builder = RenderPipelineBuilder::new(device);
+        builder.set_shader_module("shaders/shader.wgsl", "vs_main", "fs_main");
+        builder.set_pixel_format(FORMAT);
+        builder.set_blend_mode(blend_mode);
+        builder.add_vertex_buffer_layout(mesh_builder::Vertex::get_layout());
+        builder.add_bind_group_layout(&material_bind_group_layout);
+        builder.set_push_constants(&draw_params);
+        builder
+            .build(&format!("{:?} Pipeline", blend_mode))
+            .unwrap()
+    });
+    let [alpha_blend_pipeline, additive_pipeline] = blend_pipelines;
+
+    let multisampled_pipeline: wgpu::RenderPipeline;
+    {
+        let mut builder = RenderPipelineBuilder::new(device);
+        builder.set_shader_module("shaders/shader.wgsl", "vs_main", "fs_main");
+        builder.set_pixel_format(FORMAT);
+        builder.set_sample_count(SAMPLE_COUNT);
+        builder.add_vertex_buffer_layout(mesh_builder::Vertex::get_layout());
+        builder.add_bind_group_layout(&material_bind_group_layout);
+        builder.set_push_constants(&draw_params);
+        multisampled_pipeline = builder.build("Multisampled Pipeline").unwrap();
+    }
+
+    let depth_pipeline: wgpu::RenderPipeline;
+    {
+        let mut builder = RenderPipelineBuilder::new(device);
+        builder.set_shader_module("shaders/shader.wgsl", "vs_main", "fs_main");
+        builder.set_pixel_format(FORMAT);
+        builder.set_depth_stencil(DepthTexture::FORMAT, wgpu::CompareFunction::Less, true);
+        builder.add_vertex_buffer_layout(mesh_builder::Vertex::get_layout());
+        builder.add_bind_group_layout(&material_bind_group_layout);
+        builder.set_push_constants(&draw_params);
+        depth_pipeline = builder.build("Depth Pipeline").unwrap();
+    }
+
+    let unculled_pipeline: wgpu::RenderPipeline;
+    {
+        let mut builder = RenderPipelineBuilder::new(device);
+        builder.set_shader_module("shaders/shader.wgsl", "vs_main", "fs_main");
+        builder.set_pixel_format(FORMAT);
+        builder.set_cull_mode(None);
+        builder.add_vertex_buffer_layout(mesh_builder::Vertex::get_layout());
+        builder.add_bind_group_layout(&material_bind_group_layout);
+        builder.set_push_constants(&draw_params);
+        unculled_pipeline = builder.build("Unculled Pipeline").unwrap();
+    }
+
+    let strip_pipeline: wgpu::RenderPipeline;
+    {
+        let mut builder = RenderPipelineBuilder::new(device);
+        builder.set_shader_module("shaders/shader.wgsl", "vs_main", "fs_main");
+        builder.set_pixel_format(FORMAT);
+        builder.set_topology(wgpu::PrimitiveTopology::TriangleStrip);
+        builder.add_vertex_buffer_layout(mesh_builder::Vertex::get_layout());
+        builder.add_bind_group_layout(&material_bind_group_layout);
+        builder.set_push_constants(&draw_params);
+        strip_pipeline = builder.build("Strip Pipeline").unwrap();
+    }
+
+    let mut back_facing_triangle = TRIANGLE_VERTICES;
+    back_facing_triangle.reverse();
+
+    let triangle_material = Material::new(
+        "img/rezero.jpg",
+        device,
+        queue,
+        &resource_cache,
+        "Triangle Material",
+        &material_bind_group_layout,
+    );
+    let quad_material = Material::new(
+        "img/satin.jpg",
+        device,
+        queue,
+        &resource_cache,
+        "Quad Material",
+        &material_bind_group_layout,
+    );
+
+    let bindless = BindlessMaterials::new(
+        device,
+        &resource_cache,
+        &[&quad_material, &triangle_material],
+    )
+    .map(|materials| {
+        let mut builder = RenderPipelineBuilder::new(device);
+        builder.set_shader_module("shaders/shader.wgsl", "vs_main", "fs_main");
+        builder.set_pixel_format(FORMAT);
+        builder.add_vertex_buffer_layout(mesh_builder::Vertex::get_layout());
+        builder.set_bindless_materials(&materials);
+        builder.add_bind_group_layout(materials.get_layout());
+        builder.set_push_constants(&draw_params);
+        let pipeline = builder.build("Bindless Pipeline").unwrap();
+        (materials, pipeline)
+    });
+
+    Resources {
+        render_pipeline,
+        vertex_color_pipeline,
+        object_id_pipeline,
+        alpha_blend_pipeline,
+        additive_pipeline,
+        multisampled_pipeline,
+        depth_pipeline,
+        unculled_pipeline,
+        strip_pipeline,
+        triangle_mesh: mesh_builder::make_triangle(device),
+        quad_mesh: mesh_builder::make_quad(device),
+        near_triangle_mesh: create_mesh(device, &TRIANGLE_VERTICES, 0.25),
+        far_quad_mesh: create_mesh(device, &QUAD_TRIANGLE_VERTICES, 0.5),
+        back_facing_triangle_mesh: create_mesh(device, &back_facing_triangle, 0.0),
+        quad_strip_mesh: create_mesh(device, &QUAD_STRIP_VERTICES, 0.0),
+        triangle_material,
+        quad_material,
+        draw_params,
+        bindless,
+        resource_cache,
+        material_bind_group_layout,
+    }
+}
+
+/// The x, y and color of `mesh_builder`'s triangle, of its quad's two triangles
+/// and of the quad as a strip with the same diagonal.
+const TRIANGLE_VERTICES: [[f32; 5]; 3] = [
+    [-0.75, -0.75, 1.0, 0.0, 0.0],
+    [0.75, -0.75, 0.0, 1.0, 0.0],
+    [0.0, 0.75, 0.0, 0.0, 1.0],
+];
+const QUAD_TRIANGLE_VERTICES: [[f32; 5]; 6] = [
+    [-0.75, -0.75, 1.0, 0.0, 0.0],
+    [0.75, -0.75, 0.0, 1.0, 0.0],
+    [0.75, 0.75, 0.0, 0.0, 1.0],
+    [0.75, 0.75, 0.0, 0.0, 1.0],
+    [-0.75, 0.75, 0.0, 0.0, 1.0],
+    [-0.75, -0.75, 1.0, 0.0, 0.0],
+];
+const QUAD_STRIP_VERTICES: [[f32; 5]; 4] = [
+    [0.75, -0.75, 0.0, 1.0, 0.0],
+    [0.75, 0.75, 0.0, 0.0, 1.0],
+    [-0.75, -0.75, 1.0, 0.0, 0.0],
+    [-0.75, 0.75, 0.0, 0.0, 1.0],
+];
+
+/// A vertex buffer of `mesh_builder::Vertex`es at depth `z`.
+fn create_mesh(device: &wgpu::Device, vertices: &[[f32; 5]], z: f32) -> wgpu::Buffer {
+    let vertices: Vec<[f32; 6]> = vertices
+        .iter()
+        .map(|&[x, y, r, g, b]| [x, y, z, r, g, b])
+        .collect();
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Golden Mesh"),
+        contents: bytemuck::cast_slice(&vertices),
+        usage: wgpu::BufferUsages::VERTEX,
+    })
+}
+
+/// Draws the quad with the current pipeline, passing `instance` as its object ID.
+fn draw_quad_mesh(renderpass: &mut wgpu::RenderPass<'_>, resources: &Resources, instance: u32) {
+    let quad_mesh = &resources.quad_mesh;
+    renderpass.set_bind_group(0, &resources.quad_material.bind_group, &[]);
+    resources
+        .draw_params
+        .set(renderpass, bytemuck::bytes_of(&WHITE));
+    renderpass.set_vertex_buffer(0, quad_mesh.buffer.slice(..quad_mesh.offset));
+    renderpass.set_index_buffer(
+        quad_mesh.buffer.slice(quad_mesh.offset..),
+        wgpu::IndexFormat::Uint16,
+    );
+    renderpass.draw_indexed(0..6, 0, instance..instance + 1);
+}
+
+/// Draws the triangle with the current pipeline, passing `instance` as its object ID.
+fn draw_triangle_mesh(
+    renderpass: &mut wgpu::RenderPass<'_>,
+    resources: &Resources,
+    tint: &[f32; 4],
+    instance: u32,
+) {
+    renderpass.set_bind_group(0, &resources.triangle_material.bind_group, &[]);
+    resources
+        .draw_params
+        .set(renderpass, bytemuck::bytes_of(tint));
+    renderpass.set_vertex_buffer(0, resources.triangle_mesh.slice(..));
+    renderpass.draw(0..3, instance..instance + 1);
+}
+
+fn draw_quad(renderpass: &mut wgpu::RenderPass<'_>, resources: &Resources) {
+    renderpass.set_pipeline(&resources.render_pipeline);
+    draw_quad_mesh(renderpass, resources, 0);
+}
+
+fn draw_triangle(renderpass: &mut wgpu::RenderPass<'_>, resources: &Resources) {
+    renderpass.set_pipeline(&resources.render_pipeline);
+    draw_triangle_mesh(renderpass, resources, &WHITE, 0);
+}
+
+fn draw_tinted_triangle(renderpass: &mut wgpu::RenderPass<'_>, resources: &Resources) {
+    renderpass.set_pipeline(&resources.render_pipeline);
+    draw_triangle_mesh(renderpass, resources, &TINT, 0);
+}
+
+fn draw_alpha_blended_triangle(renderpass: &mut wgpu::RenderPass<'_>, resources: &Resources) {
+    renderpass.set_pipeline(&resources.alpha_blend_pipeline);
+    draw_triangle_mesh(renderpass, resources, &TRANSLUCENT, 0);
+}
+
+fn draw_additive_triangle(renderpass: &mut wgpu::RenderPass<'_>, resources: &Resources) {
+    renderpass.set_pipeline(&resources.additive_pipeline);
+    draw_triangle_mesh(renderpass, resources, &TRANSLUCENT, 0);
+}
+
+/// Draws the quad and the triangle like `draw_quad` and `draw_triangle`, with
+/// their textures picked from the bindless array.
+fn draw_bindless(renderpass: &mut wgpu::RenderPass<'_>, resources: &Resources) {
+    let (materials, pipeline) = resources.bindless.as_ref().unwrap();
+    renderpass.set_pipeline(pipeline);
+    materials.set_bind_group(renderpass, 0);
+
+    let quad_mesh = &resources.quad_mesh;
+    materials.select(renderpass, 0);
+    resources
+        .draw_params
+        .set(renderpass, bytemuck::bytes_of(&WHITE));
+    renderpass.set_vertex_buffer(0, quad_mesh.buffer.slice(..quad_mesh.offset));
+    renderpass.set_index_buffer(
+        quad_mesh.buffer.slice(quad_mesh.offset..),
+        wgpu::IndexFormat::Uint16,
+    );
+    renderpass.draw_indexed(0..6, 0, 0..1);
+
+    materials.select(renderpass, 1);
+    resources
+        .draw_params
+        .set(renderpass, bytemuck::bytes_of(&WHITE));
+    renderpass.set_vertex_buffer(0, resources.triangle_mesh.slice(..));
+    renderpass.draw(0..3, 0..1);
+}
+
+fn draw_vertex_color_triangle(renderpass: &mut wgpu::RenderPass<'_>, resources: &Resources) {
+    renderpass.set_pipeline(&resources.vertex_color_pipeline);
+    draw_triangle_mesh(renderpass, resources, &WHITE, 0);
+}
+
+fn draw_object_ids(renderpass: &mut wgpu::RenderPass<'_>, resources: &Resources) {
+    renderpass.set_pipeline(&resources.object_id_pipeline);
+    draw_quad_mesh(renderpass, resources, QUAD_ID);
+    draw_triangle_mesh(renderpass, resources, &WHITE, TRIANGLE_ID);
+}
+
+fn draw_multisampled(renderpass: &mut wgpu::RenderPass<'_>, resources: &Resources) {
+    renderpass.set_pipeline(&resources.multisampled_pipeline);
+    draw_quad_mesh(renderpass, resources, 0);
+    draw_triangle_mesh(renderpass, resources, &WHITE, 0);
+}
+
+/// Draws the near triangle before the far quad, which only shows around it if
+/// the depth test works.
+fn draw_depth_tested(renderpass: &mut wgpu::RenderPass<'_>, resources: &Resources) {
+    renderpass.set_pipeline(&resources.depth_pipeline);
+    resources
+        .draw_params
+        .set(renderpass, bytemuck::bytes_of(&WHITE));
+    renderpass.set_bind_group(0, &resources.triangle_material.bind_group, &[]);
+    renderpass.set_vertex_buffer(0, resources.near_triangle_mesh.slice(..));
+    renderpass.draw(0..3, 0..1);
+    resources
+        .draw_params
+        .set(renderpass, bytemuck::bytes_of(&WHITE));
+    renderpass.set_bind_group(0, &resources.quad_material.bind_group, &[]);
+    renderpass.set_vertex_buffer(0, resources.far_quad_mesh.slice(..));
+    renderpass.draw(0..6, 0..1);
+}
+
+fn draw_back_facing_triangle(renderpass: &mut wgpu::RenderPass<'_>, resources: &Resources) {
+    renderpass.set_pipeline(&resources.render_pipeline);
+    draw_back_facing_triangle_mesh(renderpass, resources);
+}
+
+fn draw_unculled_back_facing_triangle(
+    renderpass: &mut wgpu::RenderPass<'_>,
+    resources: &Resources,
+) {
+    renderpass.set_pipeline(&resources.unculled_pipeline);
+    draw_back_facing_triangle_mesh(renderpass, resources);
+}
+
+fn draw_back_facing_triangle_mesh(renderpass: &mut wgpu::RenderPass<'_>, resources: &Resources) {
+    renderpass.set_bind_group(0, &resources.triangle_material.bind_group, &[]);
+    resources
+        .draw_params
+        .set(renderpass, bytemuck::bytes_of(&WHITE));
+    renderpass.set_vertex_buffer(0, resources.back_facing_triangle_mesh.slice(..));
+    renderpass.draw(0..3, 0..1);
+}
+
+fn draw_quad_strip(renderpass: &mut wgpu::RenderPass<'_>, resources: &Resources) {
+    renderpass.set_pipeline(&resources.strip_pipeline);
+    renderpass.set_bind_group(0, &resources.quad_material.bind_group, &[]);
+    resources
+        .draw_params
+        .set(renderpass, bytemuck::bytes_of(&WHITE));
+    renderpass.set_vertex_buffer(0, resources.quad_strip_mesh.slice(..));
+    renderpass.draw(0..4, 0..1);
+}
+
+/// Attachments of a pass besides its targets.
+#[derive(Default)]
+struct Attachments<'a> {
+    /// Drawn into instead of the first target, which it is resolved to.
+    multisampled: Option<&'a MultisampledTexture>,
+    /// Cleared to 1.0.
+    depth: Option<&'a DepthTexture>,
+}
+
+/// Runs the draws in one pass over `targets`. The first target is cleared to the
+/// background color, the others to zero.
+fn draw_into(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    resources: &Resources,
+    targets: &[&OffscreenTarget],
+    attachments: &Attachments,
+    draws: &[Draw],
+) {
+    let mut command_encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Golden Encoder"),
+    });
+    {
+        let color_attachments: Vec<Option<wgpu::RenderPassColorAttachment>> = targets
+            .iter()
+            .enumerate()
+            .map(|(index, target)| {
+                let clear = match index {
+                    0 => wgpu::Color {
+                        r: 0.98,
+                        g: 0.93,
+                        b: 0.80,
+                        a: 0.0,
+                    },
+                    _ => wgpu::Color::TRANSPARENT,
+                };
+                let (view, resolve_target) = match attachments.multisampled {
+                    Some(multisampled) if index == 0 => (&multisampled.view, Some(&target.view)),
+                    _ => (&target.view, None),
+                };
+                Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(clear),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })
+            })
+            .collect();
+        let mut renderpass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Golden Renderpass"),
+            color_attachments: &color_attachments,
+            depth_stencil_attachment: attachments.depth.map(|depth| {
+                wgpu::RenderPassDepthStencilAttachment {
+                    view: &depth.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }
+            }),
+            ..Default::default()
+        });
+        for draw in draws {
+            draw(&mut renderpass, resources);
+        }
+    }
+    resources.draw_params.flush(queue);
+    queue.submit([command_encoder.finish()]);
+}
+
+fn render(draws: &[Draw]) -> RgbaImage {
+    let (device, queue) = get_gpu();
+    let resources = create_resources(device, queue);
+    let offscreen = OffscreenTarget::new(device, WIDTH, HEIGHT, FORMAT);
+    draw_into(
+        device,
+        queue,
+        &resources,
+        &[&offscreen],
+        &Attachments::default(),
+        draws,
+    );
+
+    offscreen.read(device, queue)
+}
+
+fn reference_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{name}.png"))
+}
+
+fn output_path(file: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("target")
+        .join("golden");
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join(file)
+}
+
+/// Returns the number of mismatched pixels and an image highlighting them in red
+/// over a faded copy of the reference.
+fn diff(actual: &RgbaImage, expected: &RgbaImage, tolerance: &Tolerance) -> (usize, RgbaImage) {
+    let mut mismatched = 0;
+    let diff_image = RgbaImage::from_fn(actual.width(), actual.height(), |x, y| {
+        let a = actual.get_pixel(x, y);
+        let e = expected.get_pixel(x, y);
+        let matches =
+            a.0.iter()
+                .zip(e.0.iter())
+                .all(|(a, e)| a.abs_diff(*e) <= tolerance.channel);
+        if matches {
+            let luma = (e[0] as u32 + e[1] as u32 + e[2] as u32) / 3;
+            let faded = (128 + luma / 2) as u8;
+            Rgba([faded, faded, faded, 255])
+        } else {
+            mismatched += 1;
+            Rgba([255, 0, 0, 255])
+        }
+    });
+    (mismatched, diff_image)
+}
+
+fn check(name: &str, draws: &[Draw], tolerance: Tolerance) {
+    compare(name, &render(draws), tolerance);
+}
+
+fn compare(name: &str, actual: &RgbaImage, tolerance: Tolerance) {
+    let reference = reference_path(name);
+    if std::env::var_os("GOLDEN_UPDATE").is_some() {
+        actual.save(&reference).unwrap();
+        return;
+    }
+
+    let expected = image::open(&reference)
+        .unwrap_or_else(|err| {
+            panic!(
+                "Couldn't open reference {}: {err} (run with GOLDEN_UPDATE=1 to create it)",
+                reference.display()
+            )
+        })
+        .to_rgba8();
+    assert_eq!(
+        actual.dimensions(),
+        expected.dimensions(),
+        "Golden '{name}' has different dimensions than its reference"
+    );
+
+    let (mismatched, diff_image) = diff(actual, &expected, &tolerance);
+    let allowed = (tolerance.mismatched_pixels * (WIDTH * HEIGHT) as f32) as usize;
+    if mismatched > allowed {
+        let actual_path = output_path(&format!("{name}.actual.png"));
+        let diff_path = output_path(&format!("{name}.diff.png"));
+        actual.save(&actual_path).unwrap();
+        diff_image.save(&diff_path).unwrap();
+        panic!(
+            "Golden '{name}' mismatched in {mismatched} pixels (allowed {allowed}); see {} and {}",
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+#[test]
+fn quad() {
+    check("quad", &[draw_quad], Tolerance::default());
+}
+
+#[test]
+fn triangle() {
+    check("triangle", &[draw_triangle], Tolerance::default());
+}
+
+#[test]
+fn quad_and_triangle() {
+    check(
+        "quad_and_triangle",
+        &[draw_quad, draw_triangle],
+        Tolerance::default(),
+    );
+}
+
+/// The quad and the triangle rendered with 4 samples and resolved to the single
+/// sample target, which smooths the triangle's edges.
+#[test]
+fn multisampled_quad_and_triangle() {
+    let (device, queue) = get_gpu();
+    let resources = create_resources(device, queue);
+    let offscreen = OffscreenTarget::new(device, WIDTH, HEIGHT, FORMAT);
+    let multisampled = MultisampledTexture::new(device, WIDTH, HEIGHT, FORMAT, SAMPLE_COUNT);
+    let attachments = Attachments {
+        multisampled: Some(&multisampled),
+        ..Default::default()
+    };
+    draw_into(
+        device,
+        queue,
+        &resources,
+        &[&offscreen],
+        &attachments,
+        &[draw_multisampled],
+    );
+    let resolved = offscreen.read(device, queue);
+    compare(
+        "multisampled_quad_and_triangle",
+        &resolved,
+        Tolerance::default(),
+    );
+
+    // Only the edges differ from the single sample render
+    let single_sample = render(&[draw_quad, draw_triangle]);
+    let differences = resolved
+        .pixels()
+        .zip(single_sample.pixels())
+        .filter(|(a, b)| a != b)
+        .count();
+    let pixels = (WIDTH * HEIGHT) as usize;
+    assert!(
+        differences > 0 && differences < pixels / 20,
+        "{differences} of {pixels} pixels differ from the single sample render"
+    );
+}
+
+/// The triangle is drawn first but nearer, so the scene looks the same as
+/// `quad_and_triangle`, which draws it last.
+#[test]
+fn depth_tested_quad_and_triangle() {
+    let (device, queue) = get_gpu();
+    let resources = create_resources(device, queue);
+    let offscreen = OffscreenTarget::new(device, WIDTH, HEIGHT, FORMAT);
+    let depth = DepthTexture::new(device, WIDTH, HEIGHT, 1, "Golden Depth Texture");
+    let attachments = Attachments {
+        depth: Some(&depth),
+        ..Default::default()
+    };
+    draw_into(
+        device,
+        queue,
+        &resources,
+        &[&offscreen],
+        &attachments,
+        &[draw_depth_tested],
+    );
+    compare(
+        "quad_and_triangle",
+        &offscreen.read(device, queue),
+        Tolerance::default(),
+    );
+}
+
+/// Back faces are culled by default, and drawn like front faces without culling.
+#[test]
+fn back_facing_triangle() {
+    let culled = render(&[draw_back_facing_triangle]);
+    let background = *culled.get_pixel(0, 0);
+    assert!(
+        culled.pixels().all(|pixel| *pixel == background),
+        "The back-facing triangle wasn't culled"
+    );
+
+    check(
+        "triangle",
+        &[draw_unculled_back_facing_triangle],
+        Tolerance::default(),
+    );
+}
+
+/// The quad drawn as a strip of four vertices looks the same as the indexed quad.
+#[test]
+fn quad_strip() {
+    check("quad", &[draw_quad_strip], Tolerance::default());
+}
+
+#[test]
+fn quad_and_tinted_triangle() {
+    check(
+        "quad_and_tinted_triangle",
+        &[draw_quad, draw_tinted_triangle],
+        Tolerance::default(),
+    );
+}
+
+#[test]
+fn quad_and_alpha_blended_triangle() {
+    check(
+        "quad_and_alpha_blended_triangle",
+        &[draw_quad, draw_alpha_blended_triangle],
+        Tolerance::default(),
+    );
+}
+
+#[test]
+fn quad_and_additive_triangle() {
+    check(
+        "quad_and_additive_triangle",
+        &[draw_quad, draw_additive_triangle],
+        Tolerance::default(),
+    );
+}
+
+/// Renders the same scene as `quad_and_triangle` through the bindless array. On
+/// adapters without bindless materials this only checks that the array isn't
+/// built, unless `GOLDEN_REQUIRE_BINDLESS` is set, as it should be on CI
+/// machines that have them.
+#[test]
+fn bindless_quad_and_triangle() {
+    let (device, queue) = get_gpu();
+    if !BindlessMaterials::is_supported(device, 2) {
+        assert!(
+            std::env::var_os("GOLDEN_REQUIRE_BINDLESS").is_none(),
+            "GOLDEN_REQUIRE_BINDLESS is set, but the fallback adapter has no bindless materials"
+        );
+        assert!(create_resources(device, queue).bindless.is_none());
+        eprintln!(
+            "Not rendering 'bindless_quad_and_triangle': no bindless materials on this adapter"
+        );
+        return;
+    }
+
+    check("quad_and_triangle", &[draw_bindless], Tolerance::default());
+}
+
+/// The image is larger than the golden device's 2048 texel limit, so it is split
+/// into tiles, and the quad shows the corners where four of them meet.
+#[test]
+fn tiled_quad() {
+    let (device, queue) = get_gpu();
+    let mut resources = create_resources(device, queue);
+    let image = image::open("img/satin.jpeg").unwrap().to_rgba8();
+    assert!(image.width() > device.limits().max_texture_dimension_2d);
+
+    let render_quad = |resources: &Resources| {
+        let offscreen = OffscreenTarget::new(device, WIDTH, HEIGHT, FORMAT);
+        draw_into(
+            device,
+            queue,
+            resources,
+            &[&offscreen],
+            &Attachments::default(),
+            &[draw_quad],
+        );
+        offscreen.read(device, queue)
+    };
+    let create_material = |resources: &Resources, image: RgbaImage, label: &str| {
+        Material::from_image(
+            image,
+            &LoadOptions::default(),
+            device,
+            queue,
+            &resources.resource_cache,
+            label,
+            &resources.material_bind_group_layout,
+        )
+    };
+
+    let (width, height) = image.dimensions();
+    let downscaled = image::imageops::resize(&image, width / 2, height / 2, FilterType::Triangle);
+    resources.quad_material = create_material(&resources, image, "Tiled Material");
+    assert!(resources.quad_material.is_tiled());
+    let tiled = render_quad(&resources);
+    compare("tiled_quad", &tiled, Tolerance::default());
+
+    // Half the size fits in one texture. The two only differ in the mip levels
+    // they are filtered from, so a seam where tiles meet shows up as a row or
+    // column that differs much more than the rest.
+    resources.quad_material = create_material(&resources, downscaled, "Untiled Material");
+    assert!(!resources.quad_material.is_tiled());
+    let untiled = render_quad(&resources);
+
+    let mut row_differences = vec![0u32; HEIGHT as usize];
+    let mut column_differences = vec![0u32; WIDTH as usize];
+    for (x, y, pixel) in tiled.enumerate_pixels() {
+        let other = untiled.get_pixel(x, y);
+        let difference: u32 = (0..3).map(|c| pixel[c].abs_diff(other[c]) as u32).sum();
+        row_differences[y as usize] += difference;
+        column_differences[x as usize] += difference;
+    }
+    let largest = |differences: &[u32], length: u32| {
+        differences.iter().max().copied().unwrap_or(0) as f32 / (3 * length) as f32
+    };
+    let row = largest(&row_differences, WIDTH);
+    let column = largest(&column_differences, HEIGHT);
+    assert!(
+        row < 2.5 && column < 2.5,
+        "Tiled and untiled quads differ by up to {row} per channel in a row and {column} in a column"
+    );
+}
+
+#[test]
+fn vertex_color_triangle() {
+    check(
+        "vertex_color_triangle",
+        &[draw_vertex_color_triangle],
+        Tolerance::default(),
+    );
+}
+
+#[test]
+fn object_ids() {
+    let (device, queue) = get_gpu();
+    let resources = create_resources(device, queue);
+    let color = OffscreenTarget::new(device, WIDTH, HEIGHT, FORMAT);
+    let ids = OffscreenTarget::new(device, WIDTH, HEIGHT, ID_FORMAT);
+    draw_into(
+        device,
+        queue,
+        &resources,
+        &[&color, &ids],
+        &Attachments::default(),
+        &[draw_object_ids],
+    );
+
+    // The color target looks exactly like the single-target render of the scene.
+    compare(
+        "quad_and_triangle",
+        &color.read(device, queue),
+        Tolerance::default(),
+    );
+
+    let ids: Vec<u32> = ids
+        .read_bytes(device, queue)
+        .chunks_exact(4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .collect();
+    let id_at = |x: u32, y: u32| ids[(y * WIDTH + x) as usize];
+    assert_eq!(id_at(0, 0), 0, "background");
+    assert_eq!(id_at(40, 40), QUAD_ID, "quad corner");
+    assert_eq!(id_at(WIDTH / 2, HEIGHT / 2), TRIANGLE_ID, "triangle center");
+}
+
+#[test]
+fn variant_cache_reuses_permutations() {
+    let (device, _) = get_gpu();
+    let draw_params = PushConstants::new(device, wgpu::ShaderStages::FRAGMENT, 16, 1, 4);
+    let material_bind_group_layout: bind_group_layout::BindGroupLayout;
+    {
+        let mut builder = bind_group_layout::Builder::new(device);
+        builder.add_material(0);
+        material_bind_group_layout = builder.build("Material Bind Group Layout");
+    }
+
+    let mut variant_cache = VariantCache::new();
+    let mut build_variant = |feature: Option<&str>| {
+        let mut builder = RenderPipelineBuilder::new(device);
+        builder.set_shader_module("shaders/shader.wgsl", "vs_main", "fs_main");
+        if let Some(feature) = feature {
+            builder.add_feature(feature);
+        }
+        builder.set_pixel_format(FORMAT);
+        builder.add_vertex_buffer_layout(mesh_builder::Vertex::get_layout());
+        builder.add_bind_group_layout(&material_bind_group_layout);
+        builder.set_push_constants(&draw_params);
+        builder
+            .build_variant("Variant Pipeline", &mut variant_cache)
+            .unwrap()
+    };
+
+    // Asking for the same permutation twice hits the cache, another one doesn't
+    let vertex_color_pipeline = build_variant(Some("VERTEX_COLOR_ONLY"));
+    assert_eq!(
+        vertex_color_pipeline,
+        build_variant(Some("VERTEX_COLOR_ONLY"))
+    );
+    let textured_pipeline = build_variant(None);
+    assert_ne!(vertex_color_pipeline, textured_pipeline);
+    assert_eq!(variant_cache.len(), 2);
+}
+
+#[test]
+fn resource_cache_shares_layouts_and_samplers() {
+    let (device, queue) = get_gpu();
+    let resource_cache = ResourceCache::new(device);
+
+    // Layouts with the same entries are the same layout
+    let material_bind_group_layout = create_material_layout(device, &resource_cache);
+    assert_eq!(
+        create_material_layout(device, &resource_cache).layout,
+        material_bind_group_layout.layout
+    );
+    assert_eq!(resource_cache.get_layout_count(), 1);
+
+    // Materials with the same sampler settings share one sampler
+    let materials: Vec<Material> = ["img/rezero.jpg", "img/satin.jpg"]
+        .iter()
+        .map(|filename| {
+            Material::new(
+                filename,
+                device,
+                queue,
+                &resource_cache,
+                filename,
+                &material_bind_group_layout,
+            )
+        })
+        .collect();
+    assert_eq!(materials[0].image.sampler, materials[1].image.sampler);
+
+    // That one, and the one mip levels are rendered with where the GPU does it
+    let mipmap_samplers =
+        mipmap::is_gpu_supported(device, wgpu::TextureFormat::Rgba8Unorm) as usize;
+    assert_eq!(resource_cache.get_sampler_count(), 1 + mipmap_samplers);
+}
+
+/// Bytes of an Rgba8 texture of this size, with its mip levels or without.
+fn get_texture_size(width: u32, height: u32, mipmaps: bool) -> u64 {
+    let level_count = if mipmaps {
+        mipmap::get_mip_level_count(width, height)
+    } else {
+        1
+    };
+    (0..level_count)
+        .map(|level| (width >> level).max(1) as u64 * (height >> level).max(1) as u64 * 4)
+        .sum()
+}
+
+#[test]
+fn resource_cache_shares_and_evicts_textures() {
+    let (device, queue) = get_gpu();
+    let resource_cache = ResourceCache::new(device);
+    let material_bind_group_layout = create_material_layout(device, &resource_cache);
+    let (width, height) = image::image_dimensions("img/satin.jpg").unwrap();
+
+    // Another name for the same file gets the same texture
+    let material = Material::new(
+        "img/satin.jpg",
+        device,
+        queue,
+        &resource_cache,
+        "Material",
+        &material_bind_group_layout,
+    );
+    let shared_material = Material::new(
+        "./img/../img/satin.jpg",
+        device,
+        queue,
+        &resource_cache,
+        "Shared Material",
+        &material_bind_group_layout,
+    );
+    assert!(Arc::ptr_eq(&shared_material.image, &material.image));
+    assert_eq!(resource_cache.get_texture_count(), 1);
+    assert_eq!(
+        resource_cache.get_texture_memory(),
+        get_texture_size(width, height, true)
+    );
+
+    // Other options are another texture
+    let options = LoadOptions {
+        mipmaps: false,
+        ..Default::default()
+    };
+    let unmipped = resource_cache
+        .get_texture("img/satin.jpg".as_ref(), &options, queue)
+        .unwrap();
+    assert!(!Arc::ptr_eq(&unmipped, &material.image));
+    assert_eq!(resource_cache.get_texture_count(), 2);
+    assert_eq!(
+        resource_cache.get_texture_memory(),
+        get_texture_size(width, height, true) + get_texture_size(width, height, false)
+    );
+
+    // A texture stays while anything but the cache holds it
+    drop(shared_material);
+    assert_eq!(resource_cache.evict_unused_textures(), 0);
+    drop(material);
+    assert_eq!(resource_cache.evict_unused_textures(), 1);
+    assert_eq!(resource_cache.get_texture_count(), 1);
+    assert_eq!(
+        resource_cache.get_texture_memory(),
+        get_texture_size(width, height, false)
+    );
+
+    drop(unmipped);
+    assert_eq!(resource_cache.evict_unused_textures(), 1);
+    assert_eq!(resource_cache.get_texture_count(), 0);
+    assert_eq!(resource_cache.get_texture_memory(), 0);
+
+    // Evicted textures are loaded again on the next request
+    let reloaded = resource_cache
+        .get_texture("img/satin.jpg".as_ref(), &options, queue)
+        .unwrap();
+    assert_eq!(resource_cache.get_texture_count(), 1);
+    assert_eq!(
+        reloaded.get_memory_size(),
+        get_texture_size(width, height, false)
+    );
+}